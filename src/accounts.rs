@@ -0,0 +1,164 @@
+use std::error::Error;
+use serde::Deserialize;
+use serde_json::Value;
+
+const MAX_STEPS_FIELD: &str = "max_steps=";
+
+#[derive(Debug, PartialEq)]
+pub struct AccountEntry {
+    pub user: String,
+    pub password: String,
+    pub max_steps: Option<u32>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonAccount {
+    user: String,
+    password: String,
+    max_steps: Option<u32>,
+}
+
+/// 解析 ACCOUNTS，支持两种格式：
+/// - 每行一个 `账号#密码`，可追加 `#max_steps=30000`，空行和 # 开头的行会被忽略
+/// - JSON数组，元素为 `"账号#密码"` 或 `{"user": "", "password": "", "max_steps": 30000}`
+pub fn parse_accounts(text: &str) -> Result<Vec<AccountEntry>, Box<dyn Error>> {
+    if text.trim_start().starts_with('[') {
+        return parse_json(text);
+    }
+
+    let mut accounts = Vec::new();
+    let mut errors = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        // 只去掉换行符，密码首尾的空格也是密码的一部分
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+        match parse_line(line) {
+            Ok(account) => accounts.push(account),
+            Err(e) => errors.push(format!("ACCOUNTS 第{}行：{e}", index + 1)),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors.join("\n").into());
+    }
+    Ok(accounts)
+}
+
+fn parse_line(line: &str) -> Result<AccountEntry, String> {
+    let (user, rest) = line.split_once('#').ok_or("缺少分隔符#，格式为 账号#密码")?;
+    // 密码中可以包含#，只有最后一段是 max_steps= 时才当作步数
+    let (password, max_steps) = match rest.rsplit_once('#') {
+        Some((password, field)) if field.trim().starts_with(MAX_STEPS_FIELD) => {
+            let value = field.trim().trim_start_matches(MAX_STEPS_FIELD);
+            let max_steps = value.parse::<u32>().map_err(|_| format!("max_steps必须为整数：{value}"))?;
+            (password, Some(max_steps))
+        }
+        _ => (rest, None),
+    };
+    new_entry(user, password, max_steps)
+}
+
+fn new_entry(user: &str, password: &str, max_steps: Option<u32>) -> Result<AccountEntry, String> {
+    let user = user.trim();
+    if user.is_empty() {
+        return Err("账号为空".into());
+    }
+    if password.is_empty() {
+        return Err("密码为空".into());
+    }
+    Ok(AccountEntry {
+        user: user.to_string(),
+        password: password.to_string(),
+        max_steps,
+    })
+}
+
+fn parse_json(text: &str) -> Result<Vec<AccountEntry>, Box<dyn Error>> {
+    let items: Vec<Value> = serde_json::from_str(text)
+        .map_err(|e| format!("ACCOUNTS 第{}行：JSON格式错误：{e}", e.line()))?;
+    let mut accounts = Vec::new();
+    let mut errors = Vec::new();
+    for (index, item) in items.into_iter().enumerate() {
+        let account = match item {
+            Value::String(line) => parse_line(line.trim_end_matches(['\r', '\n'])),
+            item => serde_json::from_value::<JsonAccount>(item)
+                .map_err(|e| e.to_string())
+                .and_then(|account| new_entry(&account.user, &account.password, account.max_steps)),
+        };
+        match account {
+            Ok(account) => accounts.push(account),
+            Err(e) => errors.push(format!("ACCOUNTS 第{}个账户：{e}", index + 1)),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors.join("\n").into());
+    }
+    Ok(accounts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(user: &str, password: &str, max_steps: Option<u32>) -> AccountEntry {
+        AccountEntry {
+            user: user.to_string(),
+            password: password.to_string(),
+            max_steps,
+        }
+    }
+
+    #[test]
+    fn skips_comments_and_blank_lines_with_crlf() {
+        let accounts = parse_accounts("# 注释\r\n\r\n1234#abcd\r\n  5678#efgh  \r\n").unwrap();
+        assert_eq!(accounts, vec![entry("1234", "abcd", None), entry("5678", "efgh  ", None)]);
+    }
+
+    #[test]
+    fn password_keeps_surrounding_spaces() {
+        let accounts = parse_accounts(" 1234 # pass word 
+5678#  #max_steps=100").unwrap();
+        assert_eq!(accounts, vec![entry("1234", " pass word ", None), entry("5678", "  ", Some(100))]);
+
+        let accounts = parse_accounts(r#"[" 1234 # pass\r\n"]"#).unwrap();
+        assert_eq!(accounts, vec![entry("1234", " pass", None)]);
+    }
+
+    #[test]
+    fn password_may_contain_hash() {
+        let accounts = parse_accounts("a@b.com#p#a#ss").unwrap();
+        assert_eq!(accounts, vec![entry("a@b.com", "p#a#ss", None)]);
+    }
+
+    #[test]
+    fn per_line_max_steps() {
+        let accounts = parse_accounts("1234#pa#ss#max_steps=30000").unwrap();
+        assert_eq!(accounts, vec![entry("1234", "pa#ss", Some(30000))]);
+    }
+
+    #[test]
+    fn reports_every_bad_line() {
+        let err = parse_accounts("1234#ok\nbroken\n\n#x\n5678#p#max_steps=abc\n#only\n9#").err().unwrap();
+        assert_eq!(err.to_string(), "ACCOUNTS 第2行：缺少分隔符#，格式为 账号#密码\n\
+            ACCOUNTS 第5行：max_steps必须为整数：abc\n\
+            ACCOUNTS 第7行：密码为空");
+    }
+
+    #[test]
+    fn json_array_of_strings_and_objects() {
+        let accounts = parse_accounts(r#"["1234#a#b", {"user": "a@b.com", "password": "p", "max_steps": 8000}]"#).unwrap();
+        assert_eq!(accounts, vec![entry("1234", "a#b", None), entry("a@b.com", "p", Some(8000))]);
+    }
+
+    #[test]
+    fn json_errors() {
+        let err = parse_accounts("[\n\"1234#a\",\n}").err().unwrap();
+        assert!(err.to_string().starts_with("ACCOUNTS 第3行：JSON格式错误"), "{err}");
+
+        let err = parse_accounts(r#"["1234", {"user": "u"}]"#).err().unwrap();
+        assert!(err.to_string().starts_with("ACCOUNTS 第1个账户：缺少分隔符#"), "{err}");
+        assert!(err.to_string().contains("\nACCOUNTS 第2个账户：missing field `password`"), "{err}");
+    }
+}
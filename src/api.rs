@@ -0,0 +1,282 @@
+use std::error::Error;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use axum::body::{Body, Bytes};
+use axum::extract::{FromRequest, Path, Query, Request, State};
+use axum::http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use axum::http::{HeaderMap, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::{NaiveDate, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::info;
+use crate::mods::daemon;
+use crate::mods::dashboard;
+use crate::mods::history::{HistoryQuery, SyncRecord};
+use crate::mods::logging;
+use crate::mods::report::RunReport;
+use crate::mods::runner::{self, Runner};
+
+pub const DEFAULT_API_LISTEN: &str = "127.0.0.1:8080";
+const DEFAULT_HISTORY_LIMIT: usize = 20;
+
+/// POST /sync 的请求内容，都不填时按当前时间同步所有启用的账户
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct SyncRequest {
+    /// 账号或配置中的name
+    pub account: Option<String>,
+    /// 同步指定的步数
+    pub steps: Option<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct HistoryParams {
+    pub account: Option<String>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub limit: Option<usize>,
+}
+
+pub struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+/// `/healthz` 不需要token，其余接口需要 `Authorization: Bearer <token>`，页面见 `dashboard`
+pub fn router(runner: Arc<Runner>) -> Router {
+    let dashboard = dashboard::router(runner.clone());
+    Router::new()
+        .route("/sync", post(sync))
+        .route("/accounts/:id/status", get(status))
+        .route("/history", get(history))
+        .route_layer(middleware::from_fn_with_state(runner.clone(), authorize))
+        .route("/healthz", get(|| async { "ok" }))
+        .with_state(runner)
+        .merge(dashboard)
+}
+
+/// 监听listen地址，收到SIGTERM或Ctrl-C后退出
+pub async fn serve(runner: Arc<Runner>, listen: SocketAddr) -> Result<(), Box<dyn Error>> {
+    if runner.config.api_tokens.is_empty() {
+        return Err("没有配置api_tokens，请在配置文件中设置api_tokens或设置API_TOKENS".into());
+    }
+    runner.config.api_tokens.iter().for_each(|token| logging::add_secret(token));
+    let listener = tokio::net::TcpListener::bind(listen).await
+        .map_err(|e| format!("监听{listen}失败：{e}"))?;
+    info!(url = %format!("http://{listen}"), "接口服务已启动");
+    let mut terminate = daemon::terminate_signal()?;
+    axum::serve(listener, router(runner))
+        .with_graceful_shutdown(async move {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+        })
+        .await?;
+    info!("收到退出信号，接口服务已停止");
+    Ok(())
+}
+
+async fn authorize(State(runner): State<Arc<Runner>>, request: Request, next: Next) -> Response {
+    let token = request.headers().get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match token {
+        Some(token) if runner.config.api_tokens.iter().any(|known| constant_time_eq(known, token)) => next.run(request).await,
+        _ => {
+            let mut response = ApiError(StatusCode::UNAUTHORIZED, "token错误".to_string()).into_response();
+            response.headers_mut().insert(WWW_AUTHENTICATE, "Bearer".parse().unwrap());
+            response
+        }
+    }
+}
+
+/// 比较时间与内容无关，避免通过响应时间猜出token
+pub fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// 返回与 `--output json` 相同的运行报告，有账户失败时状态码为502
+/// 请求体为空时同步所有启用的账户，其他无法解析的请求体返回400，不会当作空请求
+async fn sync(State(runner): State<Arc<Runner>>, headers: HeaderMap, body: Bytes) -> Result<Response, ApiError> {
+    let request = if body.is_empty() {
+        SyncRequest::default()
+    } else {
+        let mut request = Request::new(Body::from(body));
+        *request.headers_mut() = headers;
+        let Json(request) = Json::<SyncRequest>::from_request(request, &()).await
+            .map_err(|e| ApiError(StatusCode::BAD_REQUEST, e.body_text()))?;
+        request
+    };
+    let started_at = Utc::now();
+    let results = runner.sync(request.account.as_deref(), request.steps).await
+        .map_err(|e| ApiError(StatusCode::NOT_FOUND, e.to_string()))?;
+    if let (false, Some(path)) = (runner.dry_run, &runner.config.metrics_file) {
+        runner.metrics.write_textfile(path).map_err(internal)?;
+    }
+    let command = if request.steps.is_some() { "set" } else { "sync" };
+    let report = RunReport::new(command, runner.dry_run, started_at, &results);
+    let code = if report.success { StatusCode::OK } else { StatusCode::BAD_GATEWAY };
+    Ok((code, Json(report)).into_response())
+}
+
+async fn status(State(runner): State<Arc<Runner>>, Path(id): Path<String>) -> Result<Response, ApiError> {
+    let accounts = runner.select(Some(&id)).map_err(|e| ApiError(StatusCode::NOT_FOUND, e.to_string()))?;
+    let mut status = runner.status(accounts[0]).await.map_err(internal)?;
+    status.last_sync = status.last_sync.as_ref().map(SyncRecord::masked);
+    Ok(Json(status).into_response())
+}
+
+async fn history(State(runner): State<Arc<Runner>>, Query(params): Query<HistoryParams>) -> Result<Response, ApiError> {
+    let history = runner.history.clone();
+    let records = runner::blocking(move || history.query(&HistoryQuery {
+        account: params.account.as_deref(),
+        from: params.from,
+        to: params.to,
+        limit: Some(params.limit.unwrap_or(DEFAULT_HISTORY_LIMIT)),
+    })).await.map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    Ok(Json(records.iter().map(SyncRecord::masked).collect::<Vec<_>>()).into_response())
+}
+
+fn internal(e: Box<dyn Error>) -> ApiError {
+    ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::time::Duration;
+    use crate::mods::config::{Config, EnvOverrides};
+    use crate::mods::state;
+
+    fn temp(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("mi_steps_api_{}_{name}", std::process::id()))
+    }
+
+    fn config(test: &str) -> Config {
+        let content = format!(
+            "api_tokens = [\"secret\"]\nhistory_file = \"{}\"\nstate_file = \"{}\"\n\
+             [[account]]\nuser = \"13800138000\"\npassword = \"api-password\"\nname = \"手机\"\nmax_steps = 20000\n",
+            temp(&format!("{test}.db")).display(), temp(&format!("{test}.json")).display(),
+        );
+        Config::parse(&content, std::path::Path::new("config.toml"), EnvOverrides::default(), Vec::new()).unwrap()
+    }
+
+    async fn start(test: &str) -> String {
+        serve_runner(Arc::new(Runner::new(config(test), true).unwrap())).await
+    }
+
+    async fn serve_runner(runner: Arc<Runner>) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router(runner)).await });
+        format!("http://{address}")
+    }
+
+    #[tokio::test]
+    async fn requires_token_except_healthz() {
+        let base = start("token").await;
+        let client = reqwest::Client::new();
+        let health = client.get(format!("{base}/healthz")).send().await.unwrap();
+        assert_eq!(health.status(), 200);
+
+        let missing = client.get(format!("{base}/history")).send().await.unwrap();
+        assert_eq!(missing.status(), 401);
+        let wrong = client.get(format!("{base}/history")).bearer_auth("secrex").send().await.unwrap();
+        assert_eq!(wrong.status(), 401);
+        let history = client.get(format!("{base}/history?limit=5")).bearer_auth("secret").send().await.unwrap();
+        assert_eq!(history.status(), 200);
+        assert_eq!(history.json::<serde_json::Value>().await.unwrap(), json!([]));
+        let _ = std::fs::remove_file(temp("token.db"));
+    }
+
+    #[tokio::test]
+    async fn sync_and_status() {
+        let base = start("sync").await;
+        let client = reqwest::Client::new();
+        let sync = client.post(format!("{base}/sync")).bearer_auth("secret")
+            .json(&json!({ "account": "手机", "steps": 12345 }))
+            .send().await.unwrap();
+        assert_eq!(sync.status(), 200);
+        let report = sync.json::<RunReport>().await.unwrap();
+        assert_eq!((report.command.as_str(), report.dry_run, report.success), ("set", true, true));
+        assert_eq!(report.accounts[0].steps, Some(12345));
+
+        let unknown = client.post(format!("{base}/sync")).bearer_auth("secret")
+            .json(&json!({ "account": "none" }))
+            .send().await.unwrap();
+        assert_eq!(unknown.status(), 404);
+
+        let status = client.get(format!("{base}/accounts/13800138000/status")).bearer_auth("secret").send().await.unwrap();
+        assert_eq!(status.status(), 200);
+        let status = status.json::<serde_json::Value>().await.unwrap();
+        assert_eq!((&status["id"], &status["name"], &status["target"]), (&json!("138****8000"), &json!("手机"), &json!(20000)));
+        let _ = std::fs::remove_file(temp("sync.db"));
+    }
+
+    /// 其他实例持有锁时同步会失败并写入历史记录，不会请求华米接口
+    async fn start_locked(test: &str) -> (Arc<Runner>, String) {
+        let config = config(test);
+        state::from_url(&config.state_store).unwrap().try_lock("lock:13800138000", "other", Duration::from_secs(60)).unwrap();
+        let runner = Arc::new(Runner::new(config, false).unwrap());
+        let base = serve_runner(runner.clone()).await;
+        (runner, base)
+    }
+
+    fn cleanup(test: &str) {
+        for file in [format!("{test}.db"), format!("{test}.json"), format!("{test}.json.lock")] {
+            let _ = std::fs::remove_file(temp(&file));
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_sync_body() {
+        let (runner, base) = start_locked("bad_body").await;
+        let client = reqwest::Client::new();
+        for (body, content_type) in [
+            (r#"{"acount": "手机"}"#, "application/json"),
+            (r#"{"steps": "20000"}"#, "application/json"),
+            ("{", "application/json"),
+            (r#"{"account": "手机"}"#, "text/plain"),
+            ("steps=20000", "application/x-www-form-urlencoded"),
+        ] {
+            let response = client.post(format!("{base}/sync")).bearer_auth("secret")
+                .header("content-type", content_type)
+                .body(body)
+                .send().await.unwrap();
+            assert_eq!(response.status(), 400, "{body}");
+            let error = response.json::<serde_json::Value>().await.unwrap()["error"].as_str().unwrap().to_string();
+            assert!(!error.is_empty(), "{body}");
+        }
+        assert_eq!(runner.history.query(&HistoryQuery::default()).unwrap(), []);
+
+        // 空请求体同步所有启用的账户
+        let response = client.post(format!("{base}/sync")).bearer_auth("secret").send().await.unwrap();
+        assert_eq!(response.status(), 502);
+        assert_eq!(runner.history.query(&HistoryQuery::default()).unwrap().len(), 1);
+        cleanup("bad_body");
+    }
+
+    #[tokio::test]
+    async fn history_and_status_mask_user() {
+        let (_runner, base) = start_locked("masked").await;
+        let client = reqwest::Client::new();
+        client.post(format!("{base}/sync")).bearer_auth("secret").send().await.unwrap();
+        for path in ["/history", "/accounts/13800138000/status"] {
+            let response = client.get(format!("{base}{path}")).bearer_auth("secret").send().await.unwrap();
+            assert_eq!(response.status(), 200);
+            let text = response.text().await.unwrap();
+            assert!(text.contains("\"user\":\"138****8000\""), "{path}: {text}");
+            assert!(!text.contains("13800138000"), "{path}: {text}");
+        }
+        cleanup("masked");
+    }
+}
@@ -1,33 +1,131 @@
 use std::error::Error;
-use reqwest::Url;
-use serde_json::Value;
+use std::time::Duration;
+use async_trait::async_trait;
+use reqwest::{Client, Method, RequestBuilder, Url};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde_json::{json, Map, Value};
+use crate::mods::bark_crypto::BarkEncryption;
+use crate::mods::dry_run;
+use crate::mods::history::TargetRecord;
+use crate::mods::notify::{Notification, Notifier};
 
 
 pub struct Bark {
     server: String,
-    key: String,
+    keys: Vec<String>,
+    api: BarkApi,
+    encryption: Option<BarkEncryption>,
+    auth: Option<BarkAuth>,
+    headers: HeaderMap,
+    client: Client,
+}
+
+/// 反向代理要求的认证方式
+#[derive(Clone)]
+pub enum BarkAuth {
+    Basic { username: String, password: String },
+    Bearer(String),
+}
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Post 为 `POST /push` JSON 接口，Get 为旧版把标题和内容放进路径的接口
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BarkApi {
+    Post,
+    Get,
 }
 
 #[allow(dead_code)]
 impl Bark {
-    pub fn new(server: String, key: String) -> Self {
-        Bark {
+    pub fn new(server: String, key: String) -> Result<Self, Box<dyn Error>> {
+        Self::with_keys(server, vec![key])
+    }
+
+    pub fn with_keys(server: String, keys: Vec<String>) -> Result<Self, Box<dyn Error>> {
+        Ok(Bark {
             server,
-            key,
+            keys,
+            api: BarkApi::Post,
+            encryption: None,
+            auth: None,
+            headers: HeaderMap::new(),
+            client: Self::build_client(DEFAULT_TIMEOUT)?,
+        })
+    }
+
+    pub fn with_auth(mut self, auth: BarkAuth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Result<Self, Box<dyn Error>> {
+        let name = HeaderName::from_bytes(name.trim().as_bytes()).map_err(|e| format!("bark请求头名称错误：{e}"))?;
+        let value = HeaderValue::from_str(value.trim()).map_err(|e| format!("bark请求头{name}的值错误：{e}"))?;
+        self.headers.insert(name, value);
+        Ok(self)
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Result<Self, Box<dyn Error>> {
+        self.client = Self::build_client(timeout)?;
+        Ok(self)
+    }
+
+    fn build_client(timeout: Duration) -> reqwest::Result<Client> {
+        Client::builder()
+            .timeout(timeout)
+            .build()
+    }
+
+    fn request(&self, method: Method, url: String) -> RequestBuilder {
+        let request = self.client.request(method, url).headers(self.headers.clone());
+        match &self.auth {
+            Some(BarkAuth::Basic { username, password }) => request.basic_auth(username, Some(password)),
+            Some(BarkAuth::Bearer(token)) => request.bearer_auth(token),
+            None => request,
         }
     }
+
+    pub fn with_api(mut self, api: BarkApi) -> Self {
+        self.api = api;
+        self
+    }
+
+    pub fn with_encryption(mut self, encryption: BarkEncryption) -> Self {
+        self.encryption = Some(encryption);
+        self
+    }
+
     pub async fn send(&self, msg: &str) -> Result<(), Box<dyn Error>> {
-        let mut message = BarkMessage::default();
-        message.body = msg.to_string();
-        self.send_message(message).await
+        let results = self.send_message(BarkMessage::builder().body(msg).build()).await?;
+        Self::check_results(&results)
+    }
+
+    /// 多个设备时优先使用批量接口，服务器不支持时逐个发送
+    pub async fn send_message(&self, message: BarkMessage) -> Result<Vec<DeviceResult>, Box<dyn Error>> {
+        if self.keys.len() > 1 && self.api == BarkApi::Post {
+            let request = self.build_batch_request(&message)?;
+            let resp = request.send().await?;
+            let json_value = resp.json::<Value>().await.unwrap_or_default();
+            if let Some(results) = Self::parse_batch_response(&json_value) {
+                return Ok(results);
+            }
+        }
+
+        let mut results = Vec::new();
+        for key in &self.keys {
+            let result = self.send_one(key, &message).await.map_err(|e| e.to_string());
+            results.push(DeviceResult {
+                device_key: key.clone(),
+                result,
+            });
+        }
+        Ok(results)
     }
 
-    pub async fn send_message(&self, message: BarkMessage) -> Result<(), Box<dyn Error>> {
-        let url = Self::build_url(&self.server, self.key.as_str(), message)?;
-        let resp = reqwest::Client::builder()
-            .build()?
-            .get(url)
-            .send().await?;
+    async fn send_one(&self, key: &str, message: &BarkMessage) -> Result<(), Box<dyn Error>> {
+        let request = self.build_request(key, message)?;
+        let resp = request.send().await?;
 
         let json_value = resp.json::<Value>().await?;
         let code = json_value["code"].as_u64();
@@ -39,82 +137,677 @@ impl Bark {
         Ok(())
     }
 
+    fn build_request(&self, key: &str, message: &BarkMessage) -> Result<RequestBuilder, Box<dyn Error>> {
+        let request = match (self.api, &self.encryption) {
+            (BarkApi::Post, None) => {
+                let url = Self::build_push_url(&self.server)?;
+                self.request(Method::POST, url)
+                    .json(&Self::build_json(key, message))
+            }
+            (BarkApi::Post, Some(encryption)) => {
+                let url = Self::build_push_url(&self.server)?;
+                self.request(Method::POST, url)
+                    .json(&Self::build_encrypted_json(key, message, encryption))
+            }
+            (BarkApi::Get, None) => {
+                let url = Self::build_url(&self.server, key, message)?;
+                self.request(Method::GET, url)
+            }
+            (BarkApi::Get, Some(encryption)) => {
+                let url = Self::build_encrypted_url(&self.server, key, message, encryption)?;
+                self.request(Method::GET, url)
+            }
+        };
+        Ok(request)
+    }
+
+    fn build_batch_request(&self, message: &BarkMessage) -> Result<RequestBuilder, Box<dyn Error>> {
+        let url = Self::build_push_url(&self.server)?;
+        Ok(self.request(Method::POST, url)
+            .json(&Self::build_batch_json(&self.keys, message, self.encryption.as_ref())))
+    }
+
+    fn preview_message(&self, message: &BarkMessage) -> Result<Vec<String>, Box<dyn Error>> {
+        let requests = if self.keys.len() > 1 && self.api == BarkApi::Post {
+            vec![self.build_batch_request(message)?]
+        } else {
+            self.keys.iter()
+                .map(|key| self.build_request(key, message))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        requests.into_iter()
+            .map(|request| Ok(format!("bark {}", dry_run::describe(&request.build()?, &[]))))
+            .collect()
+    }
+
+    fn notification_message(notification: &Notification) -> BarkMessage {
+        let mut builder = BarkMessage::builder()
+            .title(notification.title.as_str())
+            .body(notification.body.as_str());
+        if let Some(group) = &notification.group {
+            builder = builder.group(group.as_str());
+        }
+        builder.build()
+    }
+
+    fn check_results(results: &[DeviceResult]) -> Result<(), Box<dyn Error>> {
+        let failed: Vec<String> = results.iter()
+            .filter_map(|device| device.result.as_ref().err().map(|e| format!("{}：{e}", mask_key(&device.device_key))))
+            .collect();
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(failed.join("；").into())
+        }
+    }
+
+    fn build_batch_json(keys: &[String], message: &BarkMessage, encryption: Option<&BarkEncryption>) -> Value {
+        let mut body = match encryption {
+            Some(encryption) => Self::build_encrypted_json("", message, encryption),
+            None => Self::build_json("", message),
+        };
+        if let Value::Object(map) = &mut body {
+            map.remove("device_key");
+            map.insert("device_keys".to_string(), json!(keys));
+        }
+        body
+    }
+
+    // 旧版本服务器不认识device_keys，返回的data不是数组
+    fn parse_batch_response(json_value: &Value) -> Option<Vec<DeviceResult>> {
+        let data = json_value["data"].as_array()?;
+        data.iter()
+            .map(|item| {
+                let device_key = item["device_key"].as_str()?.to_string();
+                let result = match item["code"].as_u64() {
+                    Some(200) => Ok(()),
+                    code => Err(format!("bark同步失败：{} {}", code.unwrap_or_default(), item["message"].as_str().unwrap_or_default())),
+                };
+                Some(DeviceResult {
+                    device_key,
+                    result,
+                })
+            })
+            .collect()
+    }
 
-    fn build_url(server: &str, key: &str, message: BarkMessage) -> Result<String, Box<dyn Error>> {
+    /// 保留服务器地址中的路径，支持部署在反向代理子路径下的服务器，如 `https://host/bark/`
+    fn build_push_url(server: &str) -> Result<String, Box<dyn Error>> {
         let mut url = Url::parse(server)?;
+        url.path_segments_mut()
+            .map_err(|_| "bark服务器地址错误")?
+            .pop_if_empty()
+            .push("push");
+        Ok(url.as_str().to_string())
+    }
+
+    fn build_json(key: &str, message: &BarkMessage) -> Value {
+        let mut body = Map::new();
+        body.insert("device_key".to_string(), json!(key));
+        if let Some(title) = &message.title {
+            body.insert("title".to_string(), json!(title));
+        }
+        if !message.body.is_empty() {
+            body.insert("body".to_string(), json!(message.body));
+        }
+        for (name, value) in Self::build_params(message) {
+            body.insert(name.to_string(), json!(value));
+        }
+        Value::Object(body)
+    }
+
+    fn build_encrypted_json(key: &str, message: &BarkMessage, encryption: &BarkEncryption) -> Value {
+        let mut body = Map::new();
+        body.insert("device_key".to_string(), json!(key));
+        for (name, value) in Self::encrypt_params(message, encryption) {
+            body.insert(name.to_string(), json!(value));
+        }
+        Value::Object(body)
+    }
+
+    fn build_encrypted_url(server: &str, key: &str, message: &BarkMessage, encryption: &BarkEncryption) -> Result<String, Box<dyn Error>> {
+        let mut url = Url::parse(server)?;
+        url.path_segments_mut()
+            .map_err(|_| "bark服务器地址错误")?
+            .pop_if_empty()
+            .push(key);
         {
-            let mut binding = url.path_segments_mut().unwrap();
-            let segments = binding.clear();
+            let mut query = url.query_pairs_mut();
+            let query = query.clear();
+            for (name, value) in Self::encrypt_params(message, encryption) {
+                query.append_pair(name, value.as_str());
+            }
+        }
+        Ok(url.as_str().to_string())
+    }
+
+    // 除device_key外的所有参数都放进密文
+    fn encrypt_params(message: &BarkMessage, encryption: &BarkEncryption) -> Vec<(&'static str, String)> {
+        let mut payload = Self::build_json("", message);
+        if let Value::Object(map) = &mut payload {
+            map.remove("device_key");
+        }
+        let mut params = vec![("ciphertext", encryption.encrypt(payload.to_string().as_str()))];
+        if let Some(iv) = encryption.iv() {
+            params.push(("iv", iv.to_string()));
+        }
+        params
+    }
+
+    fn build_url(server: &str, key: &str, message: &BarkMessage) -> Result<String, Box<dyn Error>> {
+        let mut url = Url::parse(server)?;
+        // 没有body（例如markdown消息）时标题不能放进路径，否则会被当成body
+        let title_in_path = !message.body.is_empty();
+        {
+            let mut binding = url.path_segments_mut().map_err(|_| "bark服务器地址错误")?;
+            let segments = binding.pop_if_empty();
             segments.push(key);
-            if let Some(title) = message.title {
-                segments.push(&title);
+            if title_in_path {
+                if let Some(title) = &message.title {
+                    segments.push(title);
+                }
+                segments.push(&message.body);
             }
-            segments.push(&message.body);
         }
         {
             let mut query = url.query_pairs_mut();
             let query = query.clear();
 
-            if let Some(copy) = message.copy {
-                query.append_pair("copy", copy.as_str());
-            };
-            if let Some(url) = message.url {
-                query.append_pair("url", url.as_str());
-            };
-            if let Some(is_archive) = message.is_archive {
-                query.append_pair("isArchive", is_archive.to_string().as_str());
-            };
-            if let Some(automatically_copy) = message.automatically_copy {
-                query.append_pair("automaticallyCopy", automatically_copy.to_string().as_str());
-            }
-            if let Some(icon) = message.icon {
-                query.append_pair("icon", icon.as_str());
+            if !title_in_path {
+                if let Some(title) = &message.title {
+                    query.append_pair("title", title);
+                }
             }
-            if let Some(group) = message.group {
-                query.append_pair("group", group.as_str());
+            for (name, value) in Self::build_params(message) {
+                query.append_pair(name, value.as_str());
             }
+        }
+        let mut url = url.as_str().to_string();
+        if url.ends_with('?') {
+            url.pop();
+        }
+        Ok(url)
+    }
 
-            match message.level {
-                Level::Active => { query.append_pair("level", "active"); }
-                Level::TimeSensitive => { query.append_pair("level", "timeSensitive"); }
-                Level::Passive => { query.append_pair("passive", "passive"); }
+    fn build_params(message: &BarkMessage) -> Vec<(&'static str, String)> {
+        let mut params = Vec::new();
+        let mut push = |name: &'static str, value: &Option<String>| {
+            if let Some(value) = value {
+                params.push((name, value.clone()));
             }
-        }
-        Ok(url.as_str().to_string())
+        };
+        push("subtitle", &message.subtitle);
+        push("markdown", &message.markdown);
+        push("level", &message.level.map(|level| level.as_str().to_string()));
+        push("volume", &message.volume.map(|volume| volume.to_string()));
+        push("badge", &message.badge.map(|badge| badge.to_string()));
+        push("call", &flag(message.call));
+        push("autoCopy", &flag(message.auto_copy));
+        push("copy", &message.copy);
+        push("sound", &message.sound);
+        push("icon", &message.icon);
+        push("image", &message.image);
+        push("group", &message.group);
+        push("isArchive", &message.is_archive.map(|archive| if archive { "1" } else { "0" }.to_string()));
+        push("url", &message.url);
+        push("action", &message.action);
+        push("id", &message.id);
+        push("delete", &flag(message.delete));
+        params
     }
 }
 
+pub struct DeviceResult {
+    pub device_key: String,
+    pub result: Result<(), String>,
+}
+
+fn mask_key(key: &str) -> String {
+    let prefix: String = key.chars().take(4).collect();
+    format!("{prefix}***")
+}
+
+fn flag(value: bool) -> Option<String> {
+    if value { Some("1".to_string()) } else { None }
+}
+
+#[async_trait]
+impl Notifier for Bark {
+    fn name(&self) -> &str {
+        "bark"
+    }
+
+    async fn notify(&self, notification: &Notification) -> Result<(), Box<dyn Error>> {
+        self.notify_targets(notification).await.0
+    }
+
+    /// 每个设备一条结果，设备key只保留前4位
+    async fn notify_targets(&self, notification: &Notification) -> (Result<(), Box<dyn Error>>, Vec<TargetRecord>) {
+        let results = match self.send_message(Self::notification_message(notification)).await {
+            Ok(results) => results,
+            Err(e) => return (Err(e), Vec::new()),
+        };
+        let targets = results.iter()
+            .map(|device| TargetRecord {
+                target: mask_key(&device.device_key),
+                error: device.result.as_ref().err().cloned(),
+            })
+            .collect();
+        (Self::check_results(&results), targets)
+    }
+
+    fn preview(&self, notification: &Notification) -> Result<Vec<String>, Box<dyn Error>> {
+        self.preview_message(&Self::notification_message(notification))
+    }
+}
+
+#[derive(Default, Clone, Debug)]
 pub struct BarkMessage {
-    pub title: Option<String>,
-    pub body: String,
-    pub automatically_copy: Option<u32>,
-    pub copy: Option<String>,
-    pub url: Option<String>,
-    pub is_archive: Option<u32>,
-    pub group: Option<String>,
-    pub icon: Option<String>,
-    pub level: Level,
+    title: Option<String>,
+    subtitle: Option<String>,
+    body: String,
+    markdown: Option<String>,
+    level: Option<Level>,
+    volume: Option<u8>,
+    badge: Option<u32>,
+    call: bool,
+    auto_copy: bool,
+    copy: Option<String>,
+    sound: Option<String>,
+    icon: Option<String>,
+    image: Option<String>,
+    group: Option<String>,
+    is_archive: Option<bool>,
+    url: Option<String>,
+    action: Option<String>,
+    id: Option<String>,
+    delete: bool,
 }
 
 #[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum Level {
+    Critical,
     Active,
     TimeSensitive,
     Passive,
 }
 
-impl Default for BarkMessage {
-    fn default() -> Self {
-        BarkMessage {
-            title: None,
-            body: "".to_string(),
-            automatically_copy: None,
-            copy: None,
-            url: None,
-            is_archive: None,
-            group: None,
-            icon: None,
-            level: Level::Active,
-        }
-    }
-}
\ No newline at end of file
+impl Level {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Level::Critical => "critical",
+            Level::Active => "active",
+            Level::TimeSensitive => "timeSensitive",
+            Level::Passive => "passive",
+        }
+    }
+}
+
+impl BarkMessage {
+    pub fn builder() -> BarkMessageBuilder {
+        BarkMessageBuilder::default()
+    }
+}
+
+#[derive(Default)]
+pub struct BarkMessageBuilder {
+    message: BarkMessage,
+}
+
+#[allow(dead_code)]
+impl BarkMessageBuilder {
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.message.title = Some(title.into());
+        self
+    }
+
+    pub fn subtitle(mut self, subtitle: impl Into<String>) -> Self {
+        self.message.subtitle = Some(subtitle.into());
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<String>) -> Self {
+        self.message.body = body.into();
+        self
+    }
+
+    /// markdown内容会替代body显示
+    pub fn markdown(mut self, markdown: impl Into<String>) -> Self {
+        self.message.markdown = Some(markdown.into());
+        self
+    }
+
+    pub fn level(mut self, level: Level) -> Self {
+        self.message.level = Some(level);
+        self
+    }
+
+    /// 重要警告（critical）的音量，范围0-10
+    pub fn volume(mut self, volume: u8) -> Self {
+        self.message.volume = Some(volume.min(10));
+        self
+    }
+
+    pub fn badge(mut self, badge: u32) -> Self {
+        self.message.badge = Some(badge);
+        self
+    }
+
+    /// 重复播放铃声
+    pub fn call(mut self, call: bool) -> Self {
+        self.message.call = call;
+        self
+    }
+
+    pub fn auto_copy(mut self, auto_copy: bool) -> Self {
+        self.message.auto_copy = auto_copy;
+        self
+    }
+
+    pub fn copy(mut self, copy: impl Into<String>) -> Self {
+        self.message.copy = Some(copy.into());
+        self
+    }
+
+    pub fn sound(mut self, sound: impl Into<String>) -> Self {
+        self.message.sound = Some(sound.into());
+        self
+    }
+
+    pub fn icon(mut self, icon: impl Into<String>) -> Self {
+        self.message.icon = Some(icon.into());
+        self
+    }
+
+    pub fn image(mut self, image: impl Into<String>) -> Self {
+        self.message.image = Some(image.into());
+        self
+    }
+
+    pub fn group(mut self, group: impl Into<String>) -> Self {
+        self.message.group = Some(group.into());
+        self
+    }
+
+    pub fn archive(mut self, is_archive: bool) -> Self {
+        self.message.is_archive = Some(is_archive);
+        self
+    }
+
+    pub fn url(mut self, url: impl Into<String>) -> Self {
+        self.message.url = Some(url.into());
+        self
+    }
+
+    /// 传入 none 时点击推送不弹出任何操作
+    pub fn action(mut self, action: impl Into<String>) -> Self {
+        self.message.action = Some(action.into());
+        self
+    }
+
+    /// 相同id的推送会替换掉之前的通知
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.message.id = Some(id.into());
+        self
+    }
+
+    /// 配合id删除已送达的通知
+    pub fn delete(mut self, delete: bool) -> Self {
+        self.message.delete = delete;
+        self
+    }
+
+    pub fn build(self) -> BarkMessage {
+        self.message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::mods::bark_crypto::CipherMode;
+    use super::*;
+
+    fn message() -> BarkMessage {
+        BarkMessage::builder()
+            .title("小米运动")
+            .body("步数 100/200 #1")
+            .group("steps")
+            .url("https://example.com/a?b=c")
+            .build()
+    }
+
+    fn with_body() -> BarkMessageBuilder {
+        BarkMessage::builder().body("b")
+    }
+
+    #[test]
+    fn each_option_maps_to_one_param() {
+        let cases = [
+            (with_body().subtitle("sub"), "subtitle", "sub"),
+            (with_body().markdown("**m**"), "markdown", "**m**"),
+            (with_body().level(Level::Critical), "level", "critical"),
+            (with_body().level(Level::Active), "level", "active"),
+            (with_body().level(Level::TimeSensitive), "level", "timeSensitive"),
+            (with_body().level(Level::Passive), "level", "passive"),
+            (with_body().volume(5), "volume", "5"),
+            (with_body().volume(20), "volume", "10"),
+            (with_body().badge(3), "badge", "3"),
+            (with_body().call(true), "call", "1"),
+            (with_body().auto_copy(true), "autoCopy", "1"),
+            (with_body().copy("c"), "copy", "c"),
+            (with_body().sound("birdsong"), "sound", "birdsong"),
+            (with_body().icon("https://i/a.png"), "icon", "https://i/a.png"),
+            (with_body().image("https://i/b.png"), "image", "https://i/b.png"),
+            (with_body().group("g"), "group", "g"),
+            (with_body().archive(true), "isArchive", "1"),
+            (with_body().archive(false), "isArchive", "0"),
+            (with_body().url("u"), "url", "u"),
+            (with_body().action("none"), "action", "none"),
+            (with_body().id("sync"), "id", "sync"),
+            (with_body().delete(true), "delete", "1"),
+        ];
+        for (builder, name, value) in cases {
+            let message = builder.build();
+            assert_eq!(Bark::build_json("key", &message), json!({
+                "device_key": "key",
+                "body": "b",
+                name: value,
+            }), "{name}");
+            let url = Bark::build_url("https://api.day.app", "key", &message).unwrap();
+            let expected = Url::parse_with_params("https://api.day.app/key/b", [(name, value)]).unwrap();
+            assert_eq!(url, expected.as_str(), "{name}");
+        }
+    }
+
+    #[test]
+    fn disabled_flags_are_omitted() {
+        let message = with_body().call(false).auto_copy(false).delete(false).build();
+        assert_eq!(Bark::build_json("key", &message), json!({"device_key": "key", "body": "b"}));
+        assert_eq!(Bark::build_url("https://api.day.app", "key", &message).unwrap(), "https://api.day.app/key/b");
+    }
+
+    #[test]
+    fn build_batch_json_uses_device_keys() {
+        let keys = vec!["k1".to_string(), "k2".to_string()];
+        let message = BarkMessage::builder().title("t").body("b").build();
+        assert_eq!(Bark::build_batch_json(&keys, &message, None), json!({
+            "device_keys": ["k1", "k2"],
+            "title": "t",
+            "body": "b",
+        }));
+    }
+
+    #[test]
+    fn build_batch_json_encrypted() {
+        let keys = vec!["k1".to_string(), "k2".to_string()];
+        let message = BarkMessage::builder().body("test").sound("birdsong").build();
+        let encryption = BarkEncryption::new("1234567890123456".to_string(), None, CipherMode::Ecb).unwrap();
+        assert_eq!(Bark::build_batch_json(&keys, &message, Some(&encryption)), json!({
+            "device_keys": ["k1", "k2"],
+            "ciphertext": "HoJPTeVBKoM8RtzYWztjEX9onEiiVgvmM8cSrMMTIGpb75SJeclntup12UhBVOgX",
+        }));
+    }
+
+    #[test]
+    fn parse_batch_response_reports_each_device() {
+        let value = json!({
+            "code": 200,
+            "data": [
+                {"code": 200, "device_key": "k1", "message": "success"},
+                {"code": 400, "device_key": "k2", "message": "device not found"},
+            ],
+        });
+        let results = Bark::parse_batch_response(&value).unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].device_key, "k1");
+        assert!(results[0].result.is_ok());
+        assert_eq!(results[1].result, Err("bark同步失败：400 device not found".to_string()));
+        assert!(Bark::check_results(&results).is_err());
+    }
+
+    #[tokio::test]
+    async fn notify_targets_lists_each_device() {
+        let app = axum::Router::new().fallback(|| async {
+            axum::Json(json!({
+                "code": 200,
+                "data": [
+                    {"code": 200, "device_key": "abcdef1", "message": "success"},
+                    {"code": 400, "device_key": "efgh222", "message": "device not found"},
+                ],
+            }))
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let bark = Bark::with_keys(server, vec!["abcdef1".to_string(), "efgh222".to_string()]).unwrap();
+        let notification = Notification { title: "t".to_string(), body: "b".to_string(), group: None };
+        let (result, targets) = bark.notify_targets(&notification).await;
+        assert_eq!(result.unwrap_err().to_string(), "efgh***：bark同步失败：400 device not found");
+        assert_eq!(targets, [
+            TargetRecord { target: "abcd***".to_string(), error: None },
+            TargetRecord { target: "efgh***".to_string(), error: Some("bark同步失败：400 device not found".to_string()) },
+        ]);
+    }
+
+    #[test]
+    fn parse_batch_response_falls_back_on_old_servers() {
+        let value = json!({"code": 400, "message": "failed to get device token"});
+        assert!(Bark::parse_batch_response(&value).is_none());
+    }
+
+    #[test]
+    fn request_carries_basic_auth_and_headers() {
+        let bark = Bark::new("https://bark.example.com".to_string(), "key".to_string()).unwrap()
+            .with_auth(BarkAuth::Basic { username: "user".to_string(), password: "pass".to_string() })
+            .with_header("X-Proxy-Token", "abc").unwrap();
+        let request = bark.request(Method::POST, "https://bark.example.com/push".to_string()).build().unwrap();
+        assert_eq!(request.headers()["authorization"], "Basic dXNlcjpwYXNz");
+        assert_eq!(request.headers()["x-proxy-token"], "abc");
+    }
+
+    #[test]
+    fn request_carries_bearer_token() {
+        let bark = Bark::new("https://bark.example.com".to_string(), "key".to_string()).unwrap()
+            .with_auth(BarkAuth::Bearer("token".to_string()));
+        let request = bark.request(Method::GET, "https://bark.example.com/key/b".to_string()).build().unwrap();
+        assert_eq!(request.headers()["authorization"], "Bearer token");
+    }
+
+    #[test]
+    fn with_header_rejects_invalid_name() {
+        let bark = Bark::new("https://bark.example.com".to_string(), "key".to_string()).unwrap();
+        assert!(bark.with_header("bad header", "v").is_err());
+    }
+
+    #[test]
+    fn build_push_url_keeps_path() {
+        assert_eq!(Bark::build_push_url("https://api.day.app").unwrap(), "https://api.day.app/push");
+        assert_eq!(Bark::build_push_url("https://host/bark/").unwrap(), "https://host/bark/push");
+        assert_eq!(Bark::build_push_url("https://host/bark").unwrap(), "https://host/bark/push");
+    }
+
+    #[test]
+    fn build_url_keeps_path() {
+        let message = BarkMessage::builder().body("b").build();
+        assert_eq!(Bark::build_url("https://host/bark/", "key", &message).unwrap(), "https://host/bark/key/b");
+        let encryption = BarkEncryption::new("1234567890123456".to_string(), None, CipherMode::Ecb).unwrap();
+        assert!(Bark::build_encrypted_url("https://host/bark", "key", &message, &encryption).unwrap().starts_with("https://host/bark/key?ciphertext="));
+    }
+
+    #[test]
+    fn build_json_contains_device_key_and_params() {
+        let value = Bark::build_json("key", &message());
+        assert_eq!(value, json!({
+            "device_key": "key",
+            "title": "小米运动",
+            "body": "步数 100/200 #1",
+            "url": "https://example.com/a?b=c",
+            "group": "steps",
+        }));
+    }
+
+    #[test]
+    fn build_json_without_title() {
+        let message = BarkMessage::builder().body("hello").build();
+        let value = Bark::build_json("key", &message);
+        assert_eq!(value, json!({
+            "device_key": "key",
+            "body": "hello",
+        }));
+    }
+
+    #[test]
+    fn markdown_message_keeps_title_out_of_path() {
+        let message = BarkMessage::builder().title("t").markdown("# m").build();
+        assert_eq!(Bark::build_json("key", &message), json!({
+            "device_key": "key",
+            "title": "t",
+            "markdown": "# m",
+        }));
+        let url = Bark::build_url("https://api.day.app", "key", &message).unwrap();
+        assert_eq!(url, "https://api.day.app/key?title=t&markdown=%23+m");
+    }
+
+    #[test]
+    fn replace_and_delete_by_id() {
+        let message = BarkMessage::builder().id("today").delete(true).build();
+        assert_eq!(Bark::build_json("key", &message), json!({
+            "device_key": "key",
+            "id": "today",
+            "delete": "1",
+        }));
+        let url = Bark::build_url("https://api.day.app", "key", &message).unwrap();
+        assert_eq!(url, "https://api.day.app/key?id=today&delete=1");
+    }
+
+    #[test]
+    fn build_encrypted_json_hides_payload() {
+        let message = BarkMessage::builder().body("test").sound("birdsong").build();
+        let encryption = BarkEncryption::new("1234567890123456".to_string(), Some("1111111111111111".to_string()), CipherMode::Cbc).unwrap();
+        let value = Bark::build_encrypted_json("key", &message, &encryption);
+        assert_eq!(value, json!({
+            "device_key": "key",
+            "ciphertext": "PyyK7dW6sTXP2TzjVOYOC+JApqNGkWH9Sj3+tnBs2feSO0etk2Qw1A+6SfdZ5KZ1",
+            "iv": "1111111111111111",
+        }));
+    }
+
+    #[test]
+    fn build_encrypted_url_keeps_only_key_in_path() {
+        let message = BarkMessage::builder().body("test").sound("birdsong").build();
+        let encryption = BarkEncryption::new("1234567890123456".to_string(), None, CipherMode::Ecb).unwrap();
+        let url = Bark::build_encrypted_url("https://api.day.app", "key", &message, &encryption).unwrap();
+        let expected = Url::parse_with_params("https://api.day.app/key", [("ciphertext", "HoJPTeVBKoM8RtzYWztjEX9onEiiVgvmM8cSrMMTIGpb75SJeclntup12UhBVOgX")]).unwrap();
+        assert_eq!(url, expected.as_str());
+    }
+
+    #[test]
+    fn build_url_puts_title_and_body_in_path() {
+        let url = Bark::build_url("https://api.day.app", "key", &message()).unwrap();
+        assert_eq!(url, "https://api.day.app/key/%E5%B0%8F%E7%B1%B3%E8%BF%90%E5%8A%A8/%E6%AD%A5%E6%95%B0%20100%2F200%20%231\
+            ?group=steps&url=https%3A%2F%2Fexample.com%2Fa%3Fb%3Dc");
+    }
+}
@@ -0,0 +1,107 @@
+use std::error::Error;
+use aes::{Aes128, Aes192, Aes256};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use cbc::cipher::{BlockEncryptMut, KeyInit, KeyIvInit};
+use cbc::cipher::block_padding::Pkcs7;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CipherMode {
+    Cbc,
+    Ecb,
+}
+
+/// bark推送加密，密钥长度16/24/32分别对应AES-128/192/256
+#[derive(Clone)]
+pub struct BarkEncryption {
+    key: String,
+    iv: Option<String>,
+    mode: CipherMode,
+}
+
+impl BarkEncryption {
+    pub fn new(key: String, iv: Option<String>, mode: CipherMode) -> Result<Self, Box<dyn Error>> {
+        if ![16, 24, 32].contains(&key.len()) {
+            return Err(format!("bark加密密钥长度必须为16、24或32位，当前为{}位", key.len()).into());
+        }
+        match (mode, &iv) {
+            (CipherMode::Cbc, None) => return Err("bark加密CBC模式需要设置iv".into()),
+            (CipherMode::Cbc, Some(iv)) if iv.len() != 16 => {
+                return Err(format!("bark加密iv长度必须为16位，当前为{}位", iv.len()).into());
+            }
+            _ => {}
+        }
+        Ok(BarkEncryption {
+            key,
+            iv: if mode == CipherMode::Cbc { iv } else { None },
+            mode,
+        })
+    }
+
+    pub fn iv(&self) -> Option<&str> {
+        self.iv.as_deref()
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> String {
+        let key = self.key.as_bytes();
+        let data = plaintext.as_bytes();
+        let encrypted = match self.mode {
+            CipherMode::Cbc => {
+                let iv = self.iv.as_deref().unwrap_or_default().as_bytes();
+                match key.len() {
+                    16 => cbc::Encryptor::<Aes128>::new_from_slices(key, iv).unwrap().encrypt_padded_vec_mut::<Pkcs7>(data),
+                    24 => cbc::Encryptor::<Aes192>::new_from_slices(key, iv).unwrap().encrypt_padded_vec_mut::<Pkcs7>(data),
+                    _ => cbc::Encryptor::<Aes256>::new_from_slices(key, iv).unwrap().encrypt_padded_vec_mut::<Pkcs7>(data),
+                }
+            }
+            CipherMode::Ecb => {
+                match key.len() {
+                    16 => ecb::Encryptor::<Aes128>::new_from_slice(key).unwrap().encrypt_padded_vec_mut::<Pkcs7>(data),
+                    24 => ecb::Encryptor::<Aes192>::new_from_slice(key).unwrap().encrypt_padded_vec_mut::<Pkcs7>(data),
+                    _ => ecb::Encryptor::<Aes256>::new_from_slice(key).unwrap().encrypt_padded_vec_mut::<Pkcs7>(data),
+                }
+            }
+        };
+        STANDARD.encode(encrypted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 与bark文档中的openssl加密脚本输出一致
+    const PAYLOAD: &str = r#"{"body":"test","sound":"birdsong"}"#;
+
+    #[test]
+    fn aes_128_cbc() {
+        let encryption = BarkEncryption::new("1234567890123456".to_string(), Some("1111111111111111".to_string()), CipherMode::Cbc).unwrap();
+        assert_eq!(encryption.encrypt(PAYLOAD), "PyyK7dW6sTXP2TzjVOYOC+JApqNGkWH9Sj3+tnBs2feSO0etk2Qw1A+6SfdZ5KZ1");
+    }
+
+    #[test]
+    fn aes_192_cbc() {
+        let encryption = BarkEncryption::new("123456789012345678901234".to_string(), Some("1111111111111111".to_string()), CipherMode::Cbc).unwrap();
+        assert_eq!(encryption.encrypt(PAYLOAD), "HuLDyIiYJrWcK72Nwk8NetlvT+dsv9WnZuMVhUgZsRkDlI8lryDqGpv5mePC2GC6");
+    }
+
+    #[test]
+    fn aes_256_cbc() {
+        let encryption = BarkEncryption::new("12345678901234567890123456789012".to_string(), Some("1111111111111111".to_string()), CipherMode::Cbc).unwrap();
+        assert_eq!(encryption.encrypt(PAYLOAD), "gXAVh2HSMZiid0jkoRc5yevO7qbehFImKQuJPsGorzOL8F45MlXdyhMEVMt1hz7S");
+    }
+
+    #[test]
+    fn aes_128_ecb() {
+        let encryption = BarkEncryption::new("1234567890123456".to_string(), None, CipherMode::Ecb).unwrap();
+        assert_eq!(encryption.iv(), None);
+        assert_eq!(encryption.encrypt(PAYLOAD), "HoJPTeVBKoM8RtzYWztjEX9onEiiVgvmM8cSrMMTIGpb75SJeclntup12UhBVOgX");
+    }
+
+    #[test]
+    fn rejects_bad_key_and_iv() {
+        assert!(BarkEncryption::new("short".to_string(), None, CipherMode::Ecb).is_err());
+        assert!(BarkEncryption::new("1234567890123456".to_string(), None, CipherMode::Cbc).is_err());
+        assert!(BarkEncryption::new("1234567890123456".to_string(), Some("123".to_string()), CipherMode::Cbc).is_err());
+    }
+}
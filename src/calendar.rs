@@ -0,0 +1,146 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use chrono::{Datelike, NaiveDate, Weekday};
+use serde::{Deserialize, Serialize};
+
+const BUNDLED_HOLIDAYS: &str = include_str!("../data/holidays_cn.toml");
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum DayKind {
+    Workday,
+    Weekend,
+    Holiday,
+}
+
+impl fmt::Display for DayKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DayKind::Workday => "工作日",
+            DayKind::Weekend => "周末",
+            DayKind::Holiday => "节假日",
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HolidayFile {
+    #[serde(default)]
+    holidays: Vec<NaiveDate>,
+    #[serde(default)]
+    workdays: Vec<NaiveDate>,
+}
+
+/// 节假日表，调休上班的周末算作工作日
+pub struct HolidayCalendar {
+    holidays: HashSet<NaiveDate>,
+    workdays: HashSet<NaiveDate>,
+}
+
+impl HolidayCalendar {
+    /// 内置的中国节假日表
+    pub fn bundled() -> Self {
+        Self::parse(BUNDLED_HOLIDAYS).expect("内置节假日表格式错误")
+    }
+
+    /// 从文件读取节假日表，代替内置的节假日表
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("读取节假日文件{}失败：{e}", path.display()))?;
+        Self::parse(&content).map_err(|e| format!("节假日文件{}格式错误：{e}", path.display()).into())
+    }
+
+    fn parse(content: &str) -> Result<Self, toml::de::Error> {
+        let file: HolidayFile = toml::from_str(content)?;
+        Ok(HolidayCalendar {
+            holidays: file.holidays.into_iter().collect(),
+            workdays: file.workdays.into_iter().collect(),
+        })
+    }
+
+    pub fn kind(&self, date: NaiveDate) -> DayKind {
+        if self.holidays.contains(&date) {
+            DayKind::Holiday
+        } else if self.workdays.contains(&date) {
+            DayKind::Workday
+        } else if matches!(date.weekday(), Weekday::Sat | Weekday::Sun) {
+            DayKind::Weekend
+        } else {
+            DayKind::Workday
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(value: &str) -> NaiveDate {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn spring_festival_2025() {
+        let calendar = HolidayCalendar::bundled();
+        // 周日调休上班
+        assert_eq!(calendar.kind(date("2025-01-25")), DayKind::Weekend);
+        assert_eq!(calendar.kind(date("2025-01-26")), DayKind::Workday);
+        assert_eq!(calendar.kind(date("2025-01-27")), DayKind::Workday);
+        assert_eq!(calendar.kind(date("2025-01-28")), DayKind::Holiday);
+        assert_eq!(calendar.kind(date("2025-02-04")), DayKind::Holiday);
+        assert_eq!(calendar.kind(date("2025-02-05")), DayKind::Workday);
+        // 周六调休上班，周日正常休息
+        assert_eq!(calendar.kind(date("2025-02-08")), DayKind::Workday);
+        assert_eq!(calendar.kind(date("2025-02-09")), DayKind::Weekend);
+    }
+
+    #[test]
+    fn national_day_2026() {
+        let calendar = HolidayCalendar::bundled();
+        assert_eq!(calendar.kind(date("2026-09-20")), DayKind::Workday);
+        assert_eq!(calendar.kind(date("2026-09-30")), DayKind::Workday);
+        assert_eq!(calendar.kind(date("2026-10-01")), DayKind::Holiday);
+        assert_eq!(calendar.kind(date("2026-10-07")), DayKind::Holiday);
+        assert_eq!(calendar.kind(date("2026-10-08")), DayKind::Workday);
+        assert_eq!(calendar.kind(date("2026-10-10")), DayKind::Workday);
+        assert_eq!(calendar.kind(date("2026-10-11")), DayKind::Weekend);
+    }
+
+    #[test]
+    fn new_year_holiday_on_weekend() {
+        let calendar = HolidayCalendar::bundled();
+        // 2026-01-03是周六，按节假日计算；1月4日周日调休上班
+        assert_eq!(calendar.kind(date("2025-12-31")), DayKind::Workday);
+        assert_eq!(calendar.kind(date("2026-01-03")), DayKind::Holiday);
+        assert_eq!(calendar.kind(date("2026-01-04")), DayKind::Workday);
+        assert_eq!(calendar.kind(date("2026-01-10")), DayKind::Weekend);
+    }
+
+    #[test]
+    fn bundled_workdays_are_weekends() {
+        let calendar = HolidayCalendar::bundled();
+        for workday in &calendar.workdays {
+            assert!(matches!(workday.weekday(), Weekday::Sat | Weekday::Sun), "{workday}");
+            assert!(!calendar.holidays.contains(workday), "{workday}");
+        }
+    }
+
+    #[test]
+    fn local_file_replaces_bundled_table() {
+        let path = std::env::temp_dir().join(format!("mi_steps_holidays_{}.toml", std::process::id()));
+        fs::write(&path, "holidays = [\"2027-01-01\"]\nworkdays = [\"2027-01-02\"]\n").unwrap();
+        let calendar = HolidayCalendar::load(&path);
+        fs::remove_file(&path).unwrap();
+        let calendar = calendar.unwrap();
+        assert_eq!(calendar.kind(date("2027-01-01")), DayKind::Holiday);
+        assert_eq!(calendar.kind(date("2027-01-02")), DayKind::Workday);
+        assert_eq!(calendar.kind(date("2025-01-28")), DayKind::Workday);
+
+        let err = HolidayCalendar::parse("holidays = [\"2027-13-01\"]").err().unwrap();
+        assert!(err.to_string().contains("input is out of range"), "{err}");
+    }
+}
@@ -0,0 +1,104 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use chrono::NaiveDate;
+use clap::{Parser, Subcommand, ValueEnum};
+
+#[derive(Parser)]
+#[command(name = "mi_steps", version, about = "小米运动步数同步")]
+pub struct Cli {
+    /// 配置文件路径，默认读取CONFIG_FILE环境变量或当前目录下的config.toml
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+    /// 只打印将要执行的操作，不发送任何请求
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+    /// json时输出带版本号的运行报告，格式见 `report::RunReport`
+    #[arg(long, global = true, value_enum, default_value_t = Output::Text)]
+    pub output: Output,
+    /// 把JSON运行报告写入文件，不再输出到标准输出
+    #[arg(long, global = true)]
+    pub output_file: Option<PathBuf>,
+    /// 日志格式，日志输出到标准错误
+    #[arg(long, global = true, value_enum, env = "LOG_FORMAT", default_value_t = LogFormat::Pretty)]
+    pub log_format: LogFormat,
+    /// 日志级别，也可以写成 `mi_steps=debug` 这样的过滤规则
+    #[arg(long, global = true, env = "LOG_LEVEL", default_value = "warn")]
+    pub log_level: String,
+    /// 不指定时等同于 sync
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// 同步所有账户或指定账户的步数
+    Sync {
+        /// 账号或配置中的name
+        #[arg(long, short)]
+        account: Option<String>,
+    },
+    /// 只验证账户能否登录
+    LoginCheck {
+        #[arg(long, short)]
+        account: Option<String>,
+    },
+    /// 打印当前或指定时间生成的步数
+    Preview {
+        #[arg(long, short)]
+        account: Option<String>,
+        /// 格式为 HH:MM[:SS] 或 YYYY-MM-DD HH:MM[:SS]
+        #[arg(long)]
+        at: Option<String>,
+    },
+    /// 同步指定的步数
+    Set {
+        steps: u32,
+        #[arg(long, short)]
+        account: Option<String>,
+    },
+    /// 常驻运行，按配置的schedule定时同步
+    Daemon,
+    /// 提供触发同步和查询状态的HTTP接口
+    Serve {
+        /// 监听地址，默认取配置中的api_listen或127.0.0.1:8080
+        #[arg(long)]
+        listen: Option<SocketAddr>,
+    },
+    /// 查询同步记录
+    History {
+        #[arg(long, short)]
+        account: Option<String>,
+        /// 开始日期，格式为 YYYY-MM-DD
+        #[arg(long)]
+        from: Option<NaiveDate>,
+        /// 结束日期（包含）
+        #[arg(long)]
+        to: Option<NaiveDate>,
+        /// 最多显示的条数
+        #[arg(long, short = 'n', default_value_t = 20)]
+        limit: usize,
+    },
+    /// 配置相关命令
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommand {
+    /// 检查配置文件和环境变量
+    Validate,
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq)]
+pub enum Output {
+    Text,
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    Pretty,
+    Json,
+}
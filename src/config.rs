@@ -0,0 +1,807 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
+use std::fmt;
+use std::net::SocketAddr;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use chrono::{NaiveDate, NaiveDateTime};
+use chrono_tz::Tz;
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use toml::Spanned;
+use crate::mods::accounts::parse_accounts;
+use crate::mods::calendar::{DayKind, HolidayCalendar};
+use crate::mods::daemon::Trigger;
+use crate::mods::notify::{self, Notifier};
+use crate::mods::history::DEFAULT_HISTORY_FILE;
+use crate::mods::state::DEFAULT_STATE_FILE;
+use crate::mods::strategy::{check_full_time, daily_target, Linear, StepStrategy, StrategyConfig};
+
+pub const DEFAULT_CONFIG_FILE: &str = "config.toml";
+const DOTENV_FILE: &str = ".env";
+const DEFAULT_FULL_TIME: u32 = 17;
+const DEFAULT_MAX_STEPS: u32 = 100000;
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct FileConfig {
+    max_steps: Option<u32>,
+    daily_target: Option<Spanned<(u32, u32)>>,
+    full_time: Option<Spanned<u32>>,
+    timezone: Option<Spanned<String>>,
+    notify: Option<Vec<Spanned<String>>>,
+    schedule: Option<Vec<Spanned<String>>>,
+    jitter: Option<u64>,
+    strategy: Option<Spanned<StrategyConfig>>,
+    state_file: Option<PathBuf>,
+    state_store: Option<String>,
+    history_file: Option<PathBuf>,
+    metrics_file: Option<PathBuf>,
+    metrics_listen: Option<String>,
+    holiday_file: Option<PathBuf>,
+    api_listen: Option<String>,
+    api_tokens: Option<Vec<String>>,
+    #[serde(default)]
+    profiles: HashMap<DayKind, FileProfile>,
+    #[serde(default, rename = "account")]
+    accounts: Vec<FileAccount>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileAccount {
+    user: String,
+    password: String,
+    name: Option<String>,
+    max_steps: Option<u32>,
+    daily_target: Option<Spanned<(u32, u32)>>,
+    full_time: Option<Spanned<u32>>,
+    timezone: Option<Spanned<String>>,
+    notify: Option<Vec<Spanned<String>>>,
+    schedule: Option<Vec<Spanned<String>>>,
+    jitter: Option<u64>,
+    strategy: Option<Spanned<StrategyConfig>>,
+    #[serde(default)]
+    profiles: HashMap<DayKind, FileProfile>,
+    enabled: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileProfile {
+    max_steps: Option<u32>,
+    daily_target: Option<Spanned<(u32, u32)>>,
+    strategy: Option<Spanned<StrategyConfig>>,
+}
+
+/// 环境变量中的配置，优先级高于配置文件中的全局设置
+#[derive(Default)]
+pub struct EnvOverrides {
+    pub accounts: Option<String>,
+    pub full_time: Option<String>,
+    pub max_steps: Option<String>,
+    pub daily_target: Option<String>,
+    pub timezone: Option<String>,
+    pub schedule: Option<String>,
+    pub jitter: Option<String>,
+    pub state_file: Option<String>,
+    pub state_store: Option<String>,
+    pub history_file: Option<String>,
+    pub metrics_file: Option<String>,
+    pub metrics_listen: Option<String>,
+    pub holiday_file: Option<String>,
+    pub api_listen: Option<String>,
+    pub api_tokens: Option<String>,
+}
+
+impl EnvOverrides {
+    pub fn from_env() -> Result<Self, String> {
+        Ok(EnvOverrides {
+            accounts: env_var("ACCOUNTS")?,
+            full_time: env_var("FULL_TIME")?,
+            max_steps: env_var("MAX_STEPS")?,
+            daily_target: env_var("DAILY_TARGET")?,
+            timezone: env_var("TIMEZONE")?,
+            schedule: env_var("SCHEDULE")?,
+            jitter: env_var("JITTER")?,
+            state_file: env_var("STATE_FILE")?,
+            state_store: env_var("STATE_STORE")?,
+            history_file: env_var("HISTORY_FILE")?,
+            metrics_file: env_var("METRICS_FILE")?,
+            metrics_listen: env_var("METRICS_LISTEN")?,
+            holiday_file: env_var("HOLIDAY_FILE")?,
+            api_listen: env_var("API_LISTEN")?,
+            api_tokens: env_var("API_TOKENS")?,
+        })
+    }
+}
+
+pub struct Config {
+    pub accounts: Vec<Account>,
+    /// 状态存储地址，如 `file://state.json`、`sqlite://history.db`、`redis://127.0.0.1/`
+    pub state_store: String,
+    /// 保存同步记录的SQLite数据库
+    pub history_file: PathBuf,
+    /// 单次运行结束后写入的node_exporter textfile
+    pub metrics_file: Option<PathBuf>,
+    /// 守护进程提供 `/metrics` 的地址
+    pub metrics_listen: Option<SocketAddr>,
+    /// 用于区分工作日、周末和节假日
+    pub calendar: HolidayCalendar,
+    /// `serve` 监听的地址
+    pub api_listen: Option<SocketAddr>,
+    /// 调用HTTP接口时使用的bearer token
+    pub api_tokens: Vec<String>,
+}
+
+pub struct Account {
+    pub user: String,
+    pub password: String,
+    pub name: Option<String>,
+    pub max_steps: u32,
+    /// 每天的目标步数范围，设置后代替max_steps
+    pub daily_target: Option<(u32, u32)>,
+    pub timezone: Option<Tz>,
+    pub enabled: bool,
+    pub notifiers: Vec<Arc<dyn Notifier>>,
+    pub schedule: Vec<Trigger>,
+    pub jitter: u64,
+    pub strategy: Arc<dyn StepStrategy>,
+    /// 按日期类型覆盖的步数设置
+    pub profiles: HashMap<DayKind, Profile>,
+}
+
+/// 工作日、周末或节假日使用的设置，未设置的项使用账户本身的设置
+#[derive(Clone, Default)]
+pub struct Profile {
+    pub max_steps: Option<u32>,
+    pub daily_target: Option<(u32, u32)>,
+    pub strategy: Option<Arc<dyn StepStrategy>>,
+}
+
+impl Account {
+    pub fn display_name(&self) -> &str {
+        self.name.as_deref().unwrap_or(self.user.as_str())
+    }
+
+    /// 当天的最大步数，配置了daily_target时为当天的随机目标
+    pub fn max_steps_on(&self, date: NaiveDate, kind: DayKind) -> u32 {
+        let profile = self.profiles.get(&kind);
+        let (max_steps, target) = match profile {
+            Some(Profile { daily_target: Some(range), .. }) => (self.max_steps, Some(*range)),
+            Some(Profile { max_steps: Some(max_steps), .. }) => (*max_steps, None),
+            _ => (self.max_steps, self.daily_target),
+        };
+        match target {
+            Some(range) => daily_target(&self.user, date, range),
+            None => max_steps,
+        }
+    }
+
+    pub fn strategy_on(&self, kind: DayKind) -> &dyn StepStrategy {
+        self.profiles.get(&kind)
+            .and_then(|profile| profile.strategy.as_deref())
+            .unwrap_or(self.strategy.as_ref())
+    }
+
+    pub fn steps_at(&self, now: NaiveDateTime, kind: DayKind) -> u32 {
+        self.strategy_on(kind).steps(now, self.max_steps_on(now.date(), kind))
+    }
+}
+
+/// 设置的来源，启动时打印，不包含值
+#[derive(Clone, Debug, PartialEq)]
+pub enum Source {
+    Env,
+    /// `NAME_FILE` 指定的文件
+    EnvFile(String),
+    DotEnv,
+    /// .env中 `NAME_FILE` 指定的文件
+    DotEnvFile(String),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Env => write!(f, "环境变量"),
+            Source::EnvFile(path) => write!(f, "文件{path}"),
+            Source::DotEnv => write!(f, ".env"),
+            Source::DotEnvFile(path) => write!(f, ".env中指定的文件{path}"),
+        }
+    }
+}
+
+lazy_static! {
+    /// 从.env中读取的变量名
+    static ref DOTENV: RwLock<HashSet<String>> = RwLock::new(HashSet::new());
+    static ref SOURCES: RwLock<BTreeMap<String, Source>> = RwLock::new(BTreeMap::new());
+}
+
+/// 读取当前目录下的.env，已经设置的环境变量不会被覆盖。
+/// 会修改环境变量，必须在启动其他线程之前调用
+pub fn load_dotenv() -> Result<(), String> {
+    load_dotenv_from(Path::new(DOTENV_FILE))
+}
+
+fn load_dotenv_from(path: &Path) -> Result<(), String> {
+    let entries = match dotenvy::from_path_iter(path) {
+        Ok(entries) => entries,
+        Err(e) if e.not_found() => return Ok(()),
+        Err(e) => return Err(format!("读取{}失败：{e}", path.display())),
+    };
+    for entry in entries {
+        let (name, value) = entry.map_err(|e| format!("{}格式错误：{e}", path.display()))?;
+        if env::var_os(&name).is_none() {
+            env::set_var(&name, value);
+            DOTENV.write().unwrap().insert(name);
+        }
+    }
+    Ok(())
+}
+
+/// 读取环境变量，未设置或为空时返回None（GitHub Actions中未设置的secret为空字符串）；
+/// 没有设置NAME时读取 `NAME_FILE` 指定的文件，去掉末尾的换行
+pub fn env_var(name: &str) -> Result<Option<String>, String> {
+    let dotenv = |name: &str| DOTENV.read().unwrap().contains(name);
+    if let Some(value) = env::var(name).ok().filter(|value| !value.is_empty()) {
+        let source = if dotenv(name) { Source::DotEnv } else { Source::Env };
+        SOURCES.write().unwrap().insert(name.to_string(), source);
+        return Ok(Some(value));
+    }
+    let file = format!("{name}_FILE");
+    let Some(path) = env::var(&file).ok().filter(|path| !path.is_empty()) else {
+        return Ok(None);
+    };
+    let content = fs::read_to_string(&path).map_err(|e| format!("读取{file}指定的文件{path}失败：{e}"))?;
+    let value = content.trim_end_matches(['\r', '\n']).to_string();
+    if value.is_empty() {
+        return Ok(None);
+    }
+    let source = if dotenv(&file) { Source::DotEnvFile(path) } else { Source::EnvFile(path) };
+    SOURCES.write().unwrap().insert(name.to_string(), source);
+    Ok(Some(value))
+}
+
+/// 通过 `env_var` 读取到的设置及其来源，按名称排序
+pub fn sources() -> Vec<(String, Source)> {
+    SOURCES.read().unwrap().iter().map(|(name, source)| (name.clone(), source.clone())).collect()
+}
+
+impl Config {
+    /// 配置文件路径依次取自参数、CONFIG_FILE环境变量和当前目录下的config.toml
+    pub fn load(path: Option<&Path>) -> Result<Config, Box<dyn Error>> {
+        // 指定了参数时不读取CONFIG_FILE，避免其中的错误或来源记录影响结果
+        let path = match path {
+            Some(path) => Some(path.to_path_buf()),
+            None => env_var("CONFIG_FILE")?.map(PathBuf::from),
+        };
+        let (content, path) = match path {
+            Some(path) => {
+                let content = fs::read_to_string(&path)
+                    .map_err(|e| format!("读取配置文件{}失败：{e}", path.display()))?;
+                (Some(content), path)
+            }
+            None => {
+                let path = PathBuf::from(DEFAULT_CONFIG_FILE);
+                (fs::read_to_string(&path).ok(), path)
+            }
+        };
+        let env_notifiers = notify::from_env()?
+            .into_iter()
+            .map(Arc::from)
+            .collect();
+        Self::parse(content.as_deref().unwrap_or_default(), &path, EnvOverrides::from_env()?, env_notifiers)
+    }
+
+    pub fn parse(content: &str, path: &Path, overrides: EnvOverrides, env_notifiers: Vec<Arc<dyn Notifier>>) -> Result<Config, Box<dyn Error>> {
+        let file: FileConfig = toml::from_str(content)
+            .map_err(|e| at(content, path, e.span().map(|span| span.start), e.message()))?;
+
+        let full_time = match overrides.full_time {
+            Some(full_time) => full_time.parse::<u32>().ok()
+                .and_then(|value| check_full_time(value).ok())
+                .ok_or(format!("FULL_TIME必须为1到24之间的整数：{full_time}"))?,
+            None => file.full_time.as_ref().map(|full_time| parse_full_time(content, path, full_time)).transpose()?.unwrap_or(DEFAULT_FULL_TIME),
+        };
+        let max_steps = match overrides.max_steps {
+            Some(max_steps) => max_steps.parse::<u32>().map_err(|_| format!("MAX_STEPS必须为整数：{max_steps}"))?,
+            None => file.max_steps.unwrap_or(DEFAULT_MAX_STEPS),
+        };
+        let target = match overrides.daily_target {
+            Some(target) => Some(parse_target_env(&target)?),
+            None => file.daily_target.as_ref().map(|target| parse_target(content, path, target)).transpose()?,
+        };
+        let timezone = match overrides.timezone {
+            Some(timezone) => Some(timezone.parse::<Tz>().map_err(|_| format!("TIMEZONE时区错误：{timezone}"))?),
+            None => file.timezone.as_ref().map(|timezone| parse_timezone(content, path, timezone)).transpose()?,
+        };
+        let schedule = match overrides.schedule {
+            Some(schedule) => schedule.lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| line.parse::<Trigger>().map_err(|e| format!("SCHEDULE {e}")))
+                .collect::<Result<Vec<_>, _>>()?,
+            None => parse_schedule(content, path, file.schedule.as_deref().unwrap_or_default())?,
+        };
+        let jitter = match overrides.jitter {
+            Some(jitter) => jitter.parse::<u64>().map_err(|_| format!("JITTER必须为秒数：{jitter}"))?,
+            None => file.jitter.unwrap_or_default(),
+        };
+        let metrics_listen = match overrides.metrics_listen.or(file.metrics_listen) {
+            Some(listen) => Some(listen.parse::<SocketAddr>().map_err(|_| format!("指标监听地址错误：{listen}，应为 IP:端口"))?),
+            None => None,
+        };
+        let api_listen = match overrides.api_listen.or(file.api_listen) {
+            Some(listen) => Some(listen.parse::<SocketAddr>().map_err(|_| format!("接口监听地址错误：{listen}，应为 IP:端口"))?),
+            None => None,
+        };
+        // API_TOKENS中每行或每个逗号分隔一个token
+        let api_tokens = match overrides.api_tokens {
+            Some(tokens) => tokens.split([',', '\n']).map(str::trim).filter(|token| !token.is_empty()).map(str::to_string).collect(),
+            None => file.api_tokens.unwrap_or_default(),
+        };
+        let mut notifiers = match &file.notify {
+            Some(urls) => parse_notifiers(content, path, urls)?,
+            None => Vec::new(),
+        };
+        notifiers.extend(env_notifiers);
+
+        let mut accounts = Vec::new();
+        for account in file.accounts {
+            let full_time = account.full_time.as_ref().map(|full_time| parse_full_time(content, path, full_time)).transpose()?.unwrap_or(full_time);
+            accounts.push(Account {
+                user: account.user,
+                password: account.password,
+                name: account.name,
+                max_steps: account.max_steps.unwrap_or(max_steps),
+                daily_target: match &account.daily_target {
+                    Some(target) => Some(parse_target(content, path, target)?),
+                    None => target,
+                },
+                timezone: match &account.timezone {
+                    Some(timezone) => Some(parse_timezone(content, path, timezone)?),
+                    None => timezone,
+                },
+                enabled: account.enabled.unwrap_or(true),
+                notifiers: match &account.notify {
+                    Some(urls) => parse_notifiers(content, path, urls)?,
+                    None => notifiers.clone(),
+                },
+                schedule: match &account.schedule {
+                    Some(schedule) => parse_schedule(content, path, schedule)?,
+                    None => schedule.clone(),
+                },
+                jitter: account.jitter.unwrap_or(jitter),
+                strategy: parse_strategy(content, path, account.strategy.as_ref().or(file.strategy.as_ref()), full_time)?,
+                profiles: parse_profiles(content, path, &file.profiles, &account.profiles, full_time)?,
+            });
+        }
+
+        // ACCOUNTS中与配置文件相同的账户只更新密码和步数，其余追加在后面
+        for entry in parse_accounts(overrides.accounts.as_deref().unwrap_or_default())? {
+            match accounts.iter_mut().find(|account| account.user == entry.user) {
+                Some(account) => {
+                    account.password = entry.password;
+                    if let Some(max_steps) = entry.max_steps {
+                        account.max_steps = max_steps;
+                    }
+                }
+                None => accounts.push(Account {
+                    user: entry.user,
+                    password: entry.password,
+                    name: None,
+                    max_steps: entry.max_steps.unwrap_or(max_steps),
+                    daily_target: target,
+                    timezone,
+                    enabled: true,
+                    notifiers: notifiers.clone(),
+                    schedule: schedule.clone(),
+                    jitter,
+                    strategy: parse_strategy(content, path, file.strategy.as_ref(), full_time)?,
+                    profiles: parse_profiles(content, path, &file.profiles, &HashMap::new(), full_time)?,
+                }),
+            }
+        }
+
+        if accounts.is_empty() {
+            return Err(format!("没有配置任何账户，请设置ACCOUNTS或在{}中添加[[account]]", path.display()).into());
+        }
+        Ok(Config {
+            accounts,
+            state_store: match overrides.state_store.or(file.state_store) {
+                Some(url) => url,
+                None => {
+                    let path = overrides.state_file.map(PathBuf::from)
+                        .or(file.state_file)
+                        .unwrap_or_else(|| PathBuf::from(DEFAULT_STATE_FILE));
+                    format!("file://{}", path.display())
+                }
+            },
+            history_file: overrides.history_file.map(PathBuf::from)
+                .or(file.history_file)
+                .unwrap_or_else(|| PathBuf::from(DEFAULT_HISTORY_FILE)),
+            metrics_file: overrides.metrics_file.map(PathBuf::from).or(file.metrics_file),
+            metrics_listen,
+            calendar: match overrides.holiday_file.map(PathBuf::from).or(file.holiday_file) {
+                Some(path) => HolidayCalendar::load(&path)?,
+                None => HolidayCalendar::bundled(),
+            },
+            api_listen,
+            api_tokens,
+        })
+    }
+}
+
+fn parse_timezone(content: &str, path: &Path, timezone: &Spanned<String>) -> Result<Tz, String> {
+    timezone.get_ref().parse::<Tz>()
+        .map_err(|_| at(content, path, Some(timezone.span().start), &format!("时区错误：{}", timezone.get_ref())))
+}
+
+fn parse_target(content: &str, path: &Path, target: &Spanned<(u32, u32)>) -> Result<(u32, u32), String> {
+    let (min, max) = *target.get_ref();
+    if min > max {
+        return Err(at(content, path, Some(target.span().start), &format!("daily_target的最小值{min}大于最大值{max}")));
+    }
+    Ok((min, max))
+}
+
+fn parse_full_time(content: &str, path: &Path, full_time: &Spanned<u32>) -> Result<u32, String> {
+    check_full_time(*full_time.get_ref()).map_err(|e| at(content, path, Some(full_time.span().start), &e))
+}
+
+/// DAILY_TARGET的格式为 `8000-15000`
+fn parse_target_env(target: &str) -> Result<(u32, u32), String> {
+    let error = || format!("DAILY_TARGET格式错误：{target}，应为 最小值-最大值");
+    let (min, max) = target.split_once('-').ok_or_else(error)?;
+    let min = min.trim().parse::<u32>().map_err(|_| error())?;
+    let max = max.trim().parse::<u32>().map_err(|_| error())?;
+    if min > max {
+        return Err(format!("DAILY_TARGET的最小值{min}大于最大值{max}"));
+    }
+    Ok((min, max))
+}
+
+fn parse_notifiers(content: &str, path: &Path, urls: &[Spanned<String>]) -> Result<Vec<Arc<dyn Notifier>>, String> {
+    urls.iter()
+        .map(|url| notify::from_url(url.get_ref())
+            .map(Arc::from)
+            .map_err(|e| at(content, path, Some(url.span().start), &format!("通知地址错误：{e}"))))
+        .collect()
+}
+
+fn parse_schedule(content: &str, path: &Path, schedule: &[Spanned<String>]) -> Result<Vec<Trigger>, String> {
+    schedule.iter()
+        .map(|trigger| trigger.get_ref().parse::<Trigger>()
+            .map_err(|e| at(content, path, Some(trigger.span().start), &e)))
+        .collect()
+}
+
+/// 未配置strategy时使用从0点到full_time的线性增长
+fn parse_strategy(content: &str, path: &Path, strategy: Option<&Spanned<StrategyConfig>>, full_time: u32) -> Result<Arc<dyn StepStrategy>, String> {
+    match strategy {
+        Some(strategy) => strategy.get_ref().build(full_time)
+            .map(Arc::from)
+            .map_err(|e| at(content, path, Some(strategy.span().start), &e)),
+        None => Ok(Arc::new(Linear { full_time })),
+    }
+}
+
+/// 账户中设置的日期类型整体代替全局的设置
+fn parse_profiles(content: &str, path: &Path, global: &HashMap<DayKind, FileProfile>,
+                  account: &HashMap<DayKind, FileProfile>, full_time: u32) -> Result<HashMap<DayKind, Profile>, String> {
+    let mut profiles = HashMap::new();
+    for (kind, profile) in global.iter().chain(account) {
+        profiles.insert(*kind, Profile {
+            max_steps: profile.max_steps,
+            daily_target: profile.daily_target.as_ref().map(|target| parse_target(content, path, target)).transpose()?,
+            strategy: match &profile.strategy {
+                Some(strategy) => Some(parse_strategy(content, path, Some(strategy), full_time)?),
+                None => None,
+            },
+        });
+    }
+    Ok(profiles)
+}
+
+fn at(content: &str, path: &Path, offset: Option<usize>, message: &str) -> String {
+    match offset {
+        Some(offset) => {
+            let before = &content[..offset.min(content.len())];
+            let line = before.matches('\n').count() + 1;
+            let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+            format!("{}:{line}:{column}: {message}", path.display())
+        }
+        None => format!("{}: {message}", path.display()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENT: &str = r#"
+max_steps = 20000
+full_time = 18
+state_file = "/var/lib/mi_steps/state.json"
+timezone = "Asia/Shanghai"
+schedule = ["09:10", "0 10 12,18 * * *"]
+jitter = 300
+
+[[account]]
+user = "13800138000"
+password = "p1"
+name = "手机"
+
+[[account]]
+user = "a@b.com"
+password = "p2"
+max_steps = 30000
+full_time = 12
+timezone = "UTC"
+notify = ["bark://key@api.day.app"]
+schedule = ["20:00"]
+jitter = 0
+enabled = false
+"#;
+
+    fn parse(content: &str, overrides: EnvOverrides) -> Result<Config, Box<dyn Error>> {
+        Config::parse(content, Path::new("config.toml"), overrides, Vec::new())
+    }
+
+    fn steps_at(account: &Account, time: &str) -> u32 {
+        let now = chrono::NaiveDate::from_ymd_opt(2024, 3, 11).unwrap()
+            .and_time(chrono::NaiveTime::parse_from_str(time, "%H:%M").unwrap());
+        account.steps_at(now, DayKind::Workday)
+    }
+
+    #[test]
+    fn account_settings_override_globals() {
+        let config = parse(CONTENT, EnvOverrides::default()).unwrap();
+        let phone = &config.accounts[0];
+        assert_eq!(phone.display_name(), "手机");
+        assert_eq!((phone.max_steps, steps_at(phone, "09:00")), (20000, 10000));
+        assert_eq!(phone.timezone, Some(chrono_tz::Asia::Shanghai));
+        assert!(phone.enabled);
+        assert!(phone.notifiers.is_empty());
+        assert_eq!((phone.schedule.len(), phone.jitter), (2, 300));
+        assert_eq!(config.state_store, "file:///var/lib/mi_steps/state.json");
+
+        let overrides = EnvOverrides { state_store: Some("redis://127.0.0.1/".to_string()), ..EnvOverrides::default() };
+        assert_eq!(parse(CONTENT, overrides).unwrap().state_store, "redis://127.0.0.1/");
+
+        let mail = &config.accounts[1];
+        assert_eq!(mail.display_name(), "a@b.com");
+        assert_eq!((mail.max_steps, steps_at(mail, "06:00")), (30000, 15000));
+        assert_eq!(mail.timezone, Some(chrono_tz::UTC));
+        assert!(!mail.enabled);
+        assert_eq!(mail.notifiers.len(), 1);
+        assert_eq!((mail.schedule.len(), mail.jitter), (1, 0));
+    }
+
+    #[test]
+    fn env_overrides_globals_and_merges_accounts() {
+        let overrides = EnvOverrides {
+            accounts: Some("13800138000#new#max_steps=1000\n13900139000#p3".to_string()),
+            max_steps: Some("50000".to_string()),
+            ..EnvOverrides::default()
+        };
+        let config = parse(CONTENT, overrides).unwrap();
+        assert_eq!(config.accounts.len(), 3);
+        assert_eq!(config.accounts[0].password, "new");
+        assert_eq!(config.accounts[0].max_steps, 1000);
+        assert_eq!(config.accounts[1].max_steps, 30000);
+        assert_eq!(config.accounts[2].user, "13900139000");
+        assert_eq!((config.accounts[2].max_steps, steps_at(&config.accounts[2], "09:00")), (50000, 25000));
+    }
+
+    #[test]
+    fn api_tokens_from_file_or_env() {
+        let content = "api_listen = \"0.0.0.0:8080\"\napi_tokens = [\"a\", \"b\"]\n[[account]]\nuser = \"u\"\npassword = \"p\"\n";
+        let config = parse(content, EnvOverrides::default()).unwrap();
+        assert_eq!(config.api_listen, "0.0.0.0:8080".parse().ok());
+        assert_eq!(config.api_tokens, ["a", "b"]);
+
+        let overrides = EnvOverrides { api_tokens: Some("c, d\ne\n".to_string()), ..EnvOverrides::default() };
+        assert_eq!(parse(content, overrides).unwrap().api_tokens, ["c", "d", "e"]);
+    }
+
+    #[test]
+    fn env_var_from_file_and_dotenv() {
+        let dir = env::temp_dir().join(format!("mi_steps_env_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let secret = dir.join("accounts");
+        fs::write(&secret, "13800138000#p1\n13900139000#p2\n").unwrap();
+        let dotenv = dir.join(".env");
+        fs::write(&dotenv, format!("MI_STEPS_TEST_DOTENV=from-dotenv\nMI_STEPS_TEST_FILE_FILE={}\n", secret.display())).unwrap();
+        load_dotenv_from(&dotenv).unwrap();
+        env::set_var("MI_STEPS_TEST_ENV", "from-env");
+        env::set_var("MI_STEPS_TEST_ENV_FILE", secret.display().to_string());
+        env::set_var("MI_STEPS_TEST_MISSING_FILE", dir.join("missing").display().to_string());
+
+        assert_eq!(env_var("MI_STEPS_TEST_FILE").unwrap().as_deref(), Some("13800138000#p1\n13900139000#p2"));
+        assert_eq!(env_var("MI_STEPS_TEST_DOTENV").unwrap().as_deref(), Some("from-dotenv"));
+        // 同时设置时NAME优先
+        assert_eq!(env_var("MI_STEPS_TEST_ENV").unwrap().as_deref(), Some("from-env"));
+        assert!(env_var("MI_STEPS_TEST_MISSING").unwrap_err().starts_with("读取MI_STEPS_TEST_MISSING_FILE指定的文件"));
+        assert_eq!(env_var("MI_STEPS_TEST_UNSET").unwrap(), None);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let sources: Vec<(String, Source)> = sources().into_iter().filter(|(name, _)| name.starts_with("MI_STEPS_TEST")).collect();
+        assert_eq!(sources, [
+            ("MI_STEPS_TEST_DOTENV".to_string(), Source::DotEnv),
+            ("MI_STEPS_TEST_ENV".to_string(), Source::Env),
+            ("MI_STEPS_TEST_FILE".to_string(), Source::DotEnvFile(secret.display().to_string())),
+        ]);
+    }
+
+    #[test]
+    fn config_argument_skips_config_file_env() {
+        let dir = env::temp_dir().join(format!("mi_steps_config_arg_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        fs::write(&path, "[[account]]\nuser = \"13800138000\"\npassword = \"p\"\n").unwrap();
+        env::set_var("CONFIG_FILE_FILE", dir.join("missing").display().to_string());
+
+        let config = Config::load(Some(&path));
+        env::remove_var("CONFIG_FILE_FILE");
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(config.unwrap().accounts[0].user, "13800138000");
+        assert!(sources().iter().all(|(name, _)| name != "CONFIG_FILE"));
+    }
+
+    #[test]
+    fn env_only_uses_defaults() {
+        let overrides = EnvOverrides {
+            accounts: Some("13800138000#p".to_string()),
+            ..EnvOverrides::default()
+        };
+        let config = parse("", overrides).unwrap();
+        assert_eq!((config.accounts[0].max_steps, steps_at(&config.accounts[0], "08:30")), (100000, 50000));
+        assert_eq!(config.accounts[0].timezone, None);
+        assert_eq!(config.state_store, "file://state.json");
+        assert_eq!(config.history_file, PathBuf::from("history.db"));
+    }
+
+    #[test]
+    fn errors_point_to_file_and_line() {
+        let err = parse("max_steps = 1\n\n[[account]]\nuser = \"u\"\npassword = 1\n", EnvOverrides::default()).err().unwrap();
+        assert!(err.to_string().starts_with("config.toml:5:12: "), "{err}");
+
+        let err = parse("schedule = [\"9:70\"]\n", EnvOverrides::default()).err().unwrap();
+        assert!(err.to_string().starts_with("config.toml:1:13: 同步时间格式错误：9:70"), "{err}");
+
+        let err = parse("timezone = \"Mars/Base\"\n", EnvOverrides::default()).err().unwrap();
+        assert_eq!(err.to_string(), "config.toml:1:12: 时区错误：Mars/Base");
+
+        let err = parse("[[account]]\nuser = \"u\"\npassword = \"p\"\nnotify = [\n  \"foo://x\",\n]\n", EnvOverrides::default()).err().unwrap();
+        assert_eq!(err.to_string(), "config.toml:5:3: 通知地址错误：不支持的通知协议：foo");
+    }
+
+    #[test]
+    fn rejects_full_time_out_of_range() {
+        let err = parse("full_time = 0
+", EnvOverrides::default()).err().unwrap();
+        assert_eq!(err.to_string(), "config.toml:1:13: full_time必须在1到24之间：0");
+
+        let err = parse("[[account]]\nuser = \"u\"\npassword = \"p\"\nfull_time = 25\n", EnvOverrides::default()).err().unwrap();
+        assert_eq!(err.to_string(), "config.toml:4:13: full_time必须在1到24之间：25");
+
+        let err = parse("strategy = { type = \"linear\", full_time = 30 }\n[[account]]\nuser = \"u\"\npassword = \"p\"\n", EnvOverrides::default()).err().unwrap();
+        assert_eq!(err.to_string(), "config.toml:1:12: full_time必须在1到24之间：30");
+
+        let overrides = EnvOverrides { full_time: Some("0".to_string()), ..EnvOverrides::default() };
+        assert_eq!(parse("", overrides).err().unwrap().to_string(), "FULL_TIME必须为1到24之间的整数：0");
+        assert!(parse("full_time = 24\n[[account]]\nuser = \"u\"\npassword = \"p\"\n", EnvOverrides::default()).is_ok());
+    }
+
+    #[test]
+    fn strategy_per_account() {
+        let content = r#"
+full_time = 12
+strategy = { type = "piecewise", points = [["06:00", 0.5], ["12:00", 1.0]] }
+
+[[account]]
+user = "u1"
+password = "p"
+
+[[account]]
+user = "u2"
+password = "p"
+strategy = { type = "fixed", steps = 8888 }
+
+[[account]]
+user = "u3"
+password = "p"
+full_time = 6
+strategy = { type = "linear" }
+"#;
+        let config = parse(content, EnvOverrides::default()).unwrap();
+        let names: Vec<&str> = config.accounts.iter().map(|account| account.strategy.name()).collect();
+        assert_eq!(names, ["piecewise", "fixed", "linear"]);
+        assert_eq!(steps_at(&config.accounts[0], "09:00"), 75000);
+        assert_eq!(steps_at(&config.accounts[1], "09:00"), 8888);
+        assert_eq!(steps_at(&config.accounts[2], "03:00"), 50000);
+
+        let err = parse("strategy = { type = \"s_curve\", start = \"20:00\", end = \"08:00\" }\n[[account]]\nuser = \"u\"\npassword = \"p\"\n", EnvOverrides::default()).err().unwrap();
+        assert_eq!(err.to_string(), "config.toml:1:12: s_curve的start必须早于end");
+
+        let err = parse("strategy = { type = \"random\" }\n", EnvOverrides::default()).err().unwrap();
+        assert!(err.to_string().starts_with("config.toml:1:"), "{err}");
+    }
+
+    #[test]
+    fn daily_target_replaces_max_steps() {
+        let content = "daily_target = [8000, 15000]\n\n[[account]]\nuser = \"u1\"\npassword = \"p\"\n\n\
+            [[account]]\nuser = \"u2\"\npassword = \"p\"\ndaily_target = [5000, 5000]\n";
+        let config = parse(content, EnvOverrides::default()).unwrap();
+        let target = steps_at(&config.accounts[0], "23:00");
+        assert!((8000..=15000).contains(&target), "{target}");
+        assert_eq!(steps_at(&config.accounts[0], "23:30"), target);
+        assert_eq!(steps_at(&config.accounts[1], "23:00"), 5000);
+
+        let overrides = EnvOverrides {
+            accounts: Some("u3#p".to_string()),
+            daily_target: Some("6000-6000".to_string()),
+            ..EnvOverrides::default()
+        };
+        let config = parse(content, overrides).unwrap();
+        assert_eq!(steps_at(&config.accounts[0], "23:00"), 6000);
+        assert_eq!(steps_at(&config.accounts[1], "23:00"), 5000);
+        assert_eq!(steps_at(&config.accounts[2], "23:00"), 6000);
+
+        let err = parse("daily_target = [9000, 8000]\n", EnvOverrides::default()).err().unwrap();
+        assert_eq!(err.to_string(), "config.toml:1:16: daily_target的最小值9000大于最大值8000");
+        let overrides = EnvOverrides { daily_target: Some("8000".to_string()), ..EnvOverrides::default() };
+        assert!(parse("", overrides).is_err());
+    }
+
+    #[test]
+    fn profiles_by_day_kind() {
+        let content = r#"
+max_steps = 10000
+full_time = 10
+
+[profiles.weekend]
+max_steps = 20000
+
+[profiles.holiday]
+strategy = { type = "fixed", steps = 3000 }
+
+[[account]]
+user = "u1"
+password = "p"
+
+[[account]]
+user = "u2"
+password = "p"
+daily_target = [6000, 6000]
+
+[account.profiles.weekend]
+strategy = { type = "linear", full_time = 20 }
+"#;
+        let config = parse(content, EnvOverrides::default()).unwrap();
+        let noon = |date: &str| format!("{date}T12:00:00").parse::<NaiveDateTime>().unwrap();
+        let steps = |account: &Account, date: &str| account.steps_at(noon(date), config.calendar.kind(noon(date).date()));
+        let (u1, u2) = (&config.accounts[0], &config.accounts[1]);
+
+        // 周一、周六、调休上班的周日、春节
+        assert_eq!(steps(u1, "2025-03-03"), 10000);
+        assert_eq!(steps(u1, "2025-03-08"), 20000);
+        assert_eq!(steps(u1, "2025-01-26"), 10000);
+        assert_eq!(steps(u1, "2025-01-29"), 3000);
+
+        // 账户的weekend设置代替全局的weekend设置，其余日期类型仍使用全局设置
+        assert_eq!(steps(u2, "2025-03-03"), 6000);
+        assert_eq!(steps(u2, "2025-03-08"), 3600);
+        assert_eq!(steps(u2, "2025-01-29"), 3000);
+
+        let err = parse("[profiles.someday]\nmax_steps = 1\n", EnvOverrides::default()).err().unwrap();
+        assert!(err.to_string().contains("unknown variant `someday`"), "{err}");
+    }
+
+    #[test]
+    fn requires_accounts() {
+        assert!(parse("max_steps = 1", EnvOverrides::default()).is_err());
+    }
+}
@@ -0,0 +1,190 @@
+use std::error::Error;
+use std::str::FromStr;
+use chrono::{DateTime, Duration, Local, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use cron::Schedule;
+use rand::Rng;
+use tracing::{info, warn};
+use crate::mods::config::Account;
+use crate::mods::metrics;
+use crate::mods::runner::Runner;
+
+/// 同步时间，可以是 `HH:MM` 形式的每日时间或cron表达式
+#[derive(Clone, Debug)]
+pub enum Trigger {
+    Time(NaiveTime),
+    Cron(Box<Schedule>),
+}
+
+impl FromStr for Trigger {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        for format in ["%H:%M", "%H:%M:%S"] {
+            if let Ok(time) = NaiveTime::parse_from_str(value, format) {
+                return Ok(Trigger::Time(time));
+            }
+        }
+        // 兼容crontab的5段写法，补上秒
+        let expression = if value.split_whitespace().count() == 5 {
+            format!("0 {value}")
+        } else {
+            value.to_string()
+        };
+        Schedule::from_str(&expression)
+            .map(|schedule| Trigger::Cron(Box::new(schedule)))
+            .map_err(|e| format!("同步时间格式错误：{value}（{e}）"))
+    }
+}
+
+impl Trigger {
+    fn next_after<Z: TimeZone>(&self, timezone: &Z, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let local = after.with_timezone(timezone);
+        match self {
+            Trigger::Time(time) => {
+                let mut date = local.date_naive();
+                loop {
+                    if let Some(next) = timezone.from_local_datetime(&date.and_time(*time)).earliest() {
+                        if next.with_timezone(&Utc) > after {
+                            return Some(next.with_timezone(&Utc));
+                        }
+                    }
+                    date = date.succ_opt()?;
+                }
+            }
+            Trigger::Cron(schedule) => schedule.after(&local).next().map(|next| next.with_timezone(&Utc)),
+        }
+    }
+}
+
+/// 账户的下一次同步时间，没有配置同步时间时返回None
+pub fn next_run(triggers: &[Trigger], timezone: Option<Tz>, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    triggers.iter()
+        .filter_map(|trigger| match timezone {
+            Some(tz) => trigger.next_after(&tz, after),
+            None => trigger.next_after(&Local, after),
+        })
+        .min()
+}
+
+fn schedule_account(account: &Account, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let next = next_run(&account.schedule, account.timezone, after)?;
+    let jitter = if account.jitter > 0 { rand::thread_rng().gen_range(0..=account.jitter) } else { 0 };
+    let next = next + Duration::seconds(jitter as i64);
+    let next_local = match account.timezone {
+        Some(tz) => next.with_timezone(&tz).format("%Y-%m-%d %H:%M:%S %Z").to_string(),
+        None => next.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S %:z").to_string(),
+    };
+    info!(account = account.display_name(), next = %next_local, "下次同步时间");
+    Some(next)
+}
+
+/// 常驻运行，按每个账户配置的时间同步，收到SIGTERM或Ctrl-C后退出
+pub async fn run(runner: &Runner) -> Result<(), Box<dyn Error>> {
+    let accounts = runner.select(None)?;
+    let now = Utc::now();
+    let mut next: Vec<Option<DateTime<Utc>>> = accounts.iter()
+        .map(|account| schedule_account(account, now))
+        .collect();
+    if next.iter().all(Option::is_none) {
+        return Err("没有配置同步时间，请在配置文件中设置schedule".into());
+    }
+
+    if let Some(listen) = runner.config.metrics_listen {
+        metrics::spawn(runner.metrics.clone(), listen).await?;
+    }
+
+    let mut terminate = terminate_signal()?;
+    loop {
+        // cron表达式指定了年份时，最后一次同步之后就没有下次了
+        let Some(wake_at) = next.iter().flatten().min().copied() else {
+            info!("所有账户都没有下次同步时间，守护进程已停止");
+            return Ok(());
+        };
+        let sleep = (wake_at - Utc::now()).to_std().unwrap_or_default();
+        tokio::select! {
+            _ = tokio::time::sleep(sleep) => {}
+            _ = tokio::signal::ctrl_c() => break,
+            _ = terminate.recv() => break,
+        }
+
+        let now = Utc::now();
+        for (index, account) in accounts.iter().enumerate() {
+            if next[index].is_some_and(|at| at <= now) {
+                let result = runner.sync_now(account).await;
+                match &result.error {
+                    None => info!(account = %result.account, steps = result.steps, "定时同步成功"),
+                    Some(e) => warn!(account = %result.account, error = %e, "定时同步失败"),
+                }
+                for e in result.notify_errors() {
+                    warn!(account = %result.account, error = %e, "通知失败");
+                }
+                next[index] = schedule_account(account, Utc::now().max(now));
+            }
+        }
+    }
+    info!("收到退出信号，守护进程已停止");
+    Ok(())
+}
+
+#[cfg(unix)]
+pub fn terminate_signal() -> Result<tokio::signal::unix::Signal, Box<dyn Error>> {
+    Ok(tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?)
+}
+
+#[cfg(not(unix))]
+pub fn terminate_signal() -> Result<tokio::sync::mpsc::Receiver<()>, Box<dyn Error>> {
+    let (sender, receiver) = tokio::sync::mpsc::channel(1);
+    std::mem::forget(sender);
+    Ok(receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn daily_time_in_timezone() {
+        let triggers = vec!["09:10".parse::<Trigger>().unwrap(), "18:30".parse().unwrap()];
+        let tz = Some(chrono_tz::Asia::Shanghai);
+        // 北京时间 2024-03-11 08:00
+        assert_eq!(next_run(&triggers, tz, utc("2024-03-11T00:00:00Z")), Some(utc("2024-03-11T01:10:00Z")));
+        // 北京时间 2024-03-11 12:00
+        assert_eq!(next_run(&triggers, tz, utc("2024-03-11T04:00:00Z")), Some(utc("2024-03-11T10:30:00Z")));
+        // 北京时间 2024-03-11 20:00，顺延到第二天
+        assert_eq!(next_run(&triggers, tz, utc("2024-03-11T12:00:00Z")), Some(utc("2024-03-12T01:10:00Z")));
+    }
+
+    #[test]
+    fn cron_expression_in_timezone() {
+        let triggers = vec!["10 1,4,7,9 * * *".parse::<Trigger>().unwrap()];
+        let tz = Some(chrono_tz::Asia::Tokyo);
+        assert_eq!(next_run(&triggers, tz, utc("2024-03-11T00:00:00Z")), Some(utc("2024-03-11T00:10:00Z")));
+        assert_eq!(next_run(&triggers, tz, utc("2024-03-11T00:10:00Z")), Some(utc("2024-03-11T16:10:00Z")));
+    }
+
+    #[test]
+    fn six_field_cron_and_errors() {
+        assert!(matches!("0 30 8 * * Mon-Fri".parse::<Trigger>(), Ok(Trigger::Cron(_))));
+        assert!(matches!("7:05".parse::<Trigger>(), Ok(Trigger::Time(_))));
+        assert!("25:00".parse::<Trigger>().is_err());
+        assert!("every day".parse::<Trigger>().is_err());
+    }
+
+    #[test]
+    fn exhausted_cron() {
+        let triggers = vec!["0 0 8 1 1 * 2020".parse::<Trigger>().unwrap()];
+        assert_eq!(next_run(&triggers, None, utc("2019-06-01T00:00:00Z")).map(|next| next.format("%Y").to_string()), Some("2020".to_string()));
+        assert_eq!(next_run(&triggers, None, utc("2024-03-11T00:00:00Z")), None);
+    }
+
+    #[test]
+    fn no_triggers() {
+        assert_eq!(next_run(&[], None, Utc::now()), None);
+    }
+}
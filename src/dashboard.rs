@@ -0,0 +1,232 @@
+use std::sync::Arc;
+use axum::extract::{Path, Request, State};
+use axum::http::header::{AUTHORIZATION, LOCATION, WWW_AUTHENTICATE};
+use axum::http::StatusCode;
+use axum::middleware::{self, Next};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::Router;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, Local, Utc};
+use crate::mods::api::constant_time_eq;
+use crate::mods::history::{HistoryQuery, SyncRecord};
+use crate::mods::notify::escape_html;
+use crate::mods::report::mask_id;
+use crate::mods::runner::{self, AccountStatus, Runner};
+
+/// 在最近的多少条记录中查找失败的同步
+const RECENT_RECORDS: usize = 100;
+const RECENT_FAILURES: usize = 10;
+
+const STYLE: &str = "
+body { font-family: -apple-system, 'PingFang SC', 'Microsoft YaHei', sans-serif; margin: 2em auto; max-width: 72em; padding: 0 1em; color: #222; }
+table { border-collapse: collapse; width: 100%; margin-bottom: 2em; }
+th, td { border-bottom: 1px solid #ddd; padding: .5em; text-align: left; vertical-align: top; }
+th { background: #f5f5f5; }
+progress { width: 8em; }
+.failed { color: #c62828; }
+.ok { color: #2e7d32; }
+.muted { color: #888; font-size: .9em; }
+";
+
+/// 浏览器使用的页面，用户名任意，密码为api_tokens中的任意一个
+pub fn router(runner: Arc<Runner>) -> Router {
+    Router::new()
+        .route("/", get(index))
+        .route("/accounts/:index/sync", post(sync))
+        .route_layer(middleware::from_fn_with_state(runner.clone(), authorize))
+        .with_state(runner)
+}
+
+async fn authorize(State(runner): State<Arc<Runner>>, request: Request, next: Next) -> Response {
+    let password = request.headers().get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|value| STANDARD.decode(value).ok())
+        .and_then(|value| String::from_utf8(value).ok())
+        .and_then(|value| value.split_once(':').map(|(_, password)| password.to_string()));
+    match password {
+        Some(password) if runner.config.api_tokens.iter().any(|known| constant_time_eq(known, &password)) => next.run(request).await,
+        _ => (StatusCode::UNAUTHORIZED, [(WWW_AUTHENTICATE, "Basic realm=\"mi_steps\", charset=\"UTF-8\"")], "需要登录").into_response(),
+    }
+}
+
+async fn index(State(runner): State<Arc<Runner>>) -> Response {
+    let mut statuses = Vec::new();
+    for account in &runner.config.accounts {
+        match runner.status(account).await.map_err(|e| e.to_string()) {
+            Ok(status) => statuses.push(status),
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+        }
+    }
+    let history = runner.history.clone();
+    let records = runner::blocking(move || history.query(&HistoryQuery {
+        limit: Some(RECENT_RECORDS),
+        ..HistoryQuery::default()
+    })).await;
+    match records {
+        Ok(records) => {
+            let failures: Vec<SyncRecord> = records.into_iter().filter(|record| !record.success).take(RECENT_FAILURES).collect();
+            Html(render(&statuses, &failures, runner.dry_run)).into_response()
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    }
+}
+
+/// 同步后回到首页，结果显示在最近一次同步中
+async fn sync(State(runner): State<Arc<Runner>>, Path(index): Path<usize>, request: Request) -> Response {
+    // 浏览器会自动带上Basic认证，拒绝其他网站提交的表单
+    let site = request.headers().get("sec-fetch-site").and_then(|value| value.to_str().ok());
+    if site.is_some_and(|site| site != "same-origin" && site != "none") {
+        return (StatusCode::FORBIDDEN, "不允许跨站提交").into_response();
+    }
+    let Some(account) = runner.config.accounts.get(index) else {
+        return (StatusCode::NOT_FOUND, "找不到账户").into_response();
+    };
+    runner.sync_now(account).await;
+    if let (false, Some(path)) = (runner.dry_run, &runner.config.metrics_file) {
+        if let Err(e) = runner.metrics.write_textfile(path) {
+            return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+        }
+    }
+    (StatusCode::SEE_OTHER, [(LOCATION, "/")]).into_response()
+}
+
+fn render(statuses: &[AccountStatus], failures: &[SyncRecord], dry_run: bool) -> String {
+    let mut rows = String::new();
+    for (index, status) in statuses.iter().enumerate() {
+        let label = match &status.name {
+            Some(name) => format!("{}<div class=\"muted\">{}</div>", escape_html(name), escape_html(&status.id)),
+            None => escape_html(&status.id),
+        };
+        let synced = status.synced.unwrap_or_default();
+        let last_sync = match &status.last_sync {
+            Some(record) if record.success => format!("<span class=\"ok\">✔ {}</span> {}", record.steps.unwrap_or_default(), time(record.started_at)),
+            Some(record) => format!("<span class=\"failed\">✘ {}</span> {}", escape_html(&hide_user(record)), time(record.started_at)),
+            None => "<span class=\"muted\">没有记录</span>".to_string(),
+        };
+        let next_sync = status.next_sync.map(time).unwrap_or_else(|| "<span class=\"muted\">未设置</span>".to_string());
+        let disabled = if status.enabled { "" } else { "<div class=\"muted\">未启用</div>" };
+        rows.push_str(&format!(
+            "<tr><td>{label}{disabled}</td><td>{} {}</td>\
+             <td><progress value=\"{synced}\" max=\"{}\"></progress> {synced} / {}</td><td>{}</td>\
+             <td>{last_sync}</td><td>{next_sync}</td>\
+             <td><form method=\"post\" action=\"/accounts/{index}/sync\"><button>立即同步</button></form></td></tr>\n",
+            status.date, status.day_kind, status.target.max(1), status.target, status.planned,
+        ));
+    }
+
+    let mut failed = String::new();
+    for record in failures {
+        let stages: Vec<String> = record.stages.iter()
+            .map(|stage| match &stage.error {
+                Some(_) => format!("<span class=\"failed\">{}({}ms)</span>", stage.stage, stage.duration_ms),
+                None => format!("{}({}ms)", stage.stage, stage.duration_ms),
+            })
+            .collect();
+        let account = if record.account == record.user { mask_id(&record.user) } else { record.account.clone() };
+        failed.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td class=\"failed\">{}</td></tr>\n",
+            time(record.started_at), escape_html(&account), stages.join(" → "), escape_html(&hide_user(record)),
+        ));
+    }
+    if failed.is_empty() {
+        failed = "<tr><td colspan=\"4\" class=\"muted\">最近没有失败的同步</td></tr>\n".to_string();
+    }
+
+    let title = if dry_run { "小米运动同步（dry-run）" } else { "小米运动同步" };
+    format!("<!DOCTYPE html>\n<html lang=\"zh-CN\">\n<head>\n<meta charset=\"utf-8\">\n\
+        <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n<title>{title}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n\
+        <h1>{title}</h1>\n\
+        <table>\n<tr><th>账户</th><th>今天</th><th>已同步 / 目标</th><th>现在同步</th><th>最近一次同步</th><th>下次同步</th><th></th></tr>\n{rows}</table>\n\
+        <h2>最近的失败</h2>\n\
+        <table>\n<tr><th>时间</th><th>账户</th><th>步骤</th><th>原因</th></tr>\n{failed}</table>\n\
+        </body>\n</html>\n")
+}
+
+fn time(at: DateTime<Utc>) -> String {
+    at.with_timezone(&Local).format("%m-%d %H:%M").to_string()
+}
+
+/// 错误信息中可能带有账号
+fn hide_user(record: &SyncRecord) -> String {
+    record.error.as_deref().unwrap_or_default().replace(&record.user, &mask_id(&record.user))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mods::calendar::DayKind;
+    use crate::mods::config::{Config, EnvOverrides};
+    use crate::mods::mi::{Stage, StageRecord};
+
+    fn failure() -> SyncRecord {
+        SyncRecord {
+            user: "13800138000".to_string(),
+            account: "13800138000".to_string(),
+            date: "2025-03-01".parse().unwrap(),
+            started_at: "2025-03-01T08:00:00Z".parse().unwrap(),
+            steps: Some(8000),
+            strategy: None,
+            success: false,
+            error: Some("获取app_token失败：<13800138000>".to_string()),
+            duration_ms: 900,
+            stages: vec![
+                StageRecord { stage: Stage::Login, status: Some(303), duration_ms: 300, error: None },
+                StageRecord { stage: Stage::AppToken, status: Some(200), duration_ms: 200, error: Some("错误码：0117".to_string()) },
+            ],
+            notify: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn renders_accounts_and_failures() {
+        let status = AccountStatus {
+            id: "138****8000".to_string(),
+            name: Some("<手机>".to_string()),
+            enabled: true,
+            date: "2025-03-01".parse().unwrap(),
+            day_kind: DayKind::Weekend,
+            target: 20000,
+            planned: 12000,
+            synced: Some(9000),
+            last_sync: Some(failure()),
+            last_success: None,
+            next_sync: None,
+        };
+        let html = render(&[status], &[failure()], false);
+        assert!(html.contains("&lt;手机&gt;<div class=\"muted\">138****8000</div>"), "{html}");
+        assert!(html.contains("<td>2025-03-01 周末</td>"), "{html}");
+        assert!(html.contains("<progress value=\"9000\" max=\"20000\"></progress> 9000 / 20000</td><td>12000</td>"), "{html}");
+        assert!(html.contains("<form method=\"post\" action=\"/accounts/0/sync\">"), "{html}");
+        assert!(html.contains("登录(300ms) → <span class=\"failed\">获取app_token(200ms)</span>"), "{html}");
+        assert!(html.contains("获取app_token失败：&lt;138****8000&gt;"), "{html}");
+        assert!(!html.contains("13800138000"), "{html}");
+    }
+
+    #[tokio::test]
+    async fn basic_auth_and_same_origin_sync() {
+        let path = std::env::temp_dir().join(format!("mi_steps_dashboard_{}.db", std::process::id()));
+        let content = format!("api_tokens = [\"secret\"]\nhistory_file = \"{}\"\n[[account]]\nuser = \"13800138000\"\npassword = \"dashboard-password\"\n", path.display());
+        let config = Config::parse(&content, std::path::Path::new("config.toml"), EnvOverrides::default(), Vec::new()).unwrap();
+        let app = router(Arc::new(Runner::new(config, true).unwrap()));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        let client = reqwest::Client::builder().redirect(reqwest::redirect::Policy::none()).build().unwrap();
+
+        let anonymous = client.get(&base).send().await.unwrap();
+        assert_eq!(anonymous.status(), 401);
+        let page = client.get(&base).basic_auth("admin", Some("secret")).send().await.unwrap();
+        assert_eq!(page.status(), 200);
+        assert!(page.text().await.unwrap().contains("138****8000"));
+
+        let sync = format!("{base}/accounts/0/sync");
+        let cross_site = client.post(&sync).basic_auth("admin", Some("secret")).header("sec-fetch-site", "cross-site").send().await.unwrap();
+        assert_eq!(cross_site.status(), 403);
+        let same_origin = client.post(&sync).basic_auth("admin", Some("secret")).header("sec-fetch-site", "same-origin").send().await.unwrap();
+        assert_eq!((same_origin.status().as_u16(), same_origin.headers()["location"].to_str().unwrap()), (303, "/"));
+        let _ = std::fs::remove_file(&path);
+    }
+}
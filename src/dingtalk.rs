@@ -0,0 +1,109 @@
+use std::error::Error;
+use async_trait::async_trait;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use hmac::{Hmac, Mac};
+use reqwest::{Client, RequestBuilder, Url};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use crate::mods::notify::{Notification, Notifier};
+
+pub struct DingTalk {
+    token: String,
+    secret: Option<String>,
+    client: Client,
+}
+
+impl DingTalk {
+    pub fn new(token: String, secret: Option<String>) -> Result<Self, Box<dyn Error>> {
+        Ok(DingTalk {
+            token,
+            secret,
+            client: Client::builder().build()?,
+        })
+    }
+
+    pub async fn send_markdown(&self, title: &str, text: &str) -> Result<(), Box<dyn Error>> {
+        let request = self.build_request(title, text, chrono::Utc::now().timestamp_millis())?;
+        let resp = request.send().await?;
+
+        let json_value = resp.json::<Value>().await?;
+        let code = json_value["errcode"].as_i64();
+        if code != Some(0) {
+            let msg = json_value["errmsg"].as_str().unwrap_or_default();
+            return Err(format!("钉钉同步失败：{} {msg}", code.unwrap_or(-1)).into());
+        }
+        Ok(())
+    }
+
+    fn build_request(&self, title: &str, text: &str, timestamp: i64) -> Result<RequestBuilder, Box<dyn Error>> {
+        Ok(self.client.post(self.build_url(timestamp)?)
+            .json(&json!({
+                "msgtype": "markdown",
+                "markdown": {
+                    "title": title,
+                    "text": text,
+                },
+            })))
+    }
+
+    fn build_url(&self, timestamp: i64) -> Result<String, Box<dyn Error>> {
+        let mut url = Url::parse("https://oapi.dingtalk.com/robot/send")?;
+        {
+            let mut query = url.query_pairs_mut();
+            query.append_pair("access_token", &self.token);
+            if let Some(secret) = &self.secret {
+                query.append_pair("timestamp", timestamp.to_string().as_str());
+                query.append_pair("sign", Self::sign(secret, timestamp)?.as_str());
+            }
+        }
+        Ok(url.as_str().to_string())
+    }
+
+    fn sign(secret: &str, timestamp: i64) -> Result<String, Box<dyn Error>> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())?;
+        mac.update(format!("{timestamp}\n{secret}").as_bytes());
+        Ok(STANDARD.encode(mac.finalize().into_bytes()))
+    }
+}
+
+#[async_trait]
+impl Notifier for DingTalk {
+    fn name(&self) -> &str {
+        "dingtalk"
+    }
+
+    async fn notify(&self, notification: &Notification) -> Result<(), Box<dyn Error>> {
+        let text = format!("#### {}\n\n{}", notification.title, notification.body);
+        self.send_markdown(&notification.title, &text).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "SEC000000000000000000000";
+
+    #[test]
+    fn sign_known_answer() {
+        assert_eq!(DingTalk::sign(SECRET, 1700000000000).unwrap(), "1zJ/w34EOSVAYr7cu7Vo8LnebmK2/GrCgegtr8mQrqM=");
+    }
+
+    #[test]
+    fn url_with_and_without_secret() {
+        let signed = DingTalk::new("token".to_string(), Some(SECRET.to_string())).unwrap();
+        assert_eq!(signed.build_url(1700000000000).unwrap(),
+                   "https://oapi.dingtalk.com/robot/send?access_token=token&timestamp=1700000000000&sign=1zJ%2Fw34EOSVAYr7cu7Vo8LnebmK2%2FGrCgegtr8mQrqM%3D");
+        let plain = DingTalk::new("token".to_string(), None).unwrap();
+        assert_eq!(plain.build_url(1700000000000).unwrap(), "https://oapi.dingtalk.com/robot/send?access_token=token");
+    }
+
+    #[test]
+    fn markdown_body() {
+        let dingtalk = DingTalk::new("token".to_string(), None).unwrap();
+        let request = dingtalk.build_request("标题", "#### 标题\n\n内容", 0).unwrap().build().unwrap();
+        let body: Value = serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(body, json!({ "msgtype": "markdown", "markdown": { "title": "标题", "text": "#### 标题\n\n内容" } }));
+    }
+}
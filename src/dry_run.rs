@@ -0,0 +1,51 @@
+use reqwest::Request;
+
+/// 把请求格式化为 `方法 地址` 加上请求体，masked中的表单字段会被隐藏
+pub fn describe(request: &Request, masked: &[&str]) -> String {
+    let mut text = format!("{} {}", request.method(), request.url());
+    let body = request.body().and_then(|body| body.as_bytes()).unwrap_or_default();
+    if body.is_empty() {
+        return text;
+    }
+    let is_form = request.headers().get("content-type")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/x-www-form-urlencoded"));
+    if is_form {
+        for (key, value) in form_urlencoded::parse(body) {
+            let value = if masked.contains(&key.as_ref()) { mask(&value) } else { value.to_string() };
+            text.push_str(&format!("\n    {key}={value}"));
+        }
+    } else {
+        text.push_str(&format!("\n    {}", String::from_utf8_lossy(body)));
+    }
+    text
+}
+
+pub fn mask(secret: &str) -> String {
+    if secret.is_empty() { String::new() } else { "******".to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describe_masks_form_fields() {
+        let request = reqwest::Client::new()
+            .post("https://example.com/login")
+            .form(&[("user", "1234"), ("password", "secret")])
+            .build()
+            .unwrap();
+        assert_eq!(describe(&request, &["password"]), "POST https://example.com/login\n    user=1234\n    password=******");
+    }
+
+    #[test]
+    fn describe_json_body() {
+        let request = reqwest::Client::new()
+            .post("https://example.com/push")
+            .json(&serde_json::json!({"body": "b"}))
+            .build()
+            .unwrap();
+        assert_eq!(describe(&request, &[]), "POST https://example.com/push\n    {\"body\":\"b\"}");
+    }
+}
@@ -0,0 +1,177 @@
+use std::env;
+use std::error::Error;
+use std::fs::OpenOptions;
+use std::io::Write;
+use crate::mods::config::Config;
+use crate::mods::report::{RunReport, Status};
+
+/// 在GitHub Actions中运行时输出任务摘要和注解
+pub fn enabled() -> bool {
+    env::var("GITHUB_ACTIONS").is_ok_and(|value| value == "true")
+}
+
+/// 让GitHub在之后的日志中隐藏账号，需要在输出其他内容之前调用
+pub fn add_masks(config: &Config) {
+    // 工作流命令写到标准错误，标准输出只留给 `--output json` 的报告
+    let _ = write_masks(&mut std::io::stderr().lock(), config);
+}
+
+fn write_masks(out: &mut impl Write, config: &Config) -> std::io::Result<()> {
+    for account in &config.accounts {
+        writeln!(out, "::add-mask::{}", escape_data(&account.user))?;
+    }
+    Ok(())
+}
+
+/// 失败的账户输出error，通知失败输出warning
+pub fn annotations(report: &RunReport) -> Vec<String> {
+    let annotation = |command: &str, title: &str, message: String| {
+        format!("::{command} title={}::{}", escape_property(title), escape_data(&message))
+    };
+    let mut lines = Vec::new();
+    for account in &report.accounts {
+        let label = account.name.as_deref().unwrap_or(&account.id);
+        if account.status == Status::Failed {
+            let error = account.error.as_deref().unwrap_or("未知错误");
+            lines.push(annotation("error", "小米运动同步失败", format!("[{label}] {error}")));
+        }
+        for notify in &account.notify {
+            if let Some(e) = &notify.error {
+                lines.push(annotation("warning", &format!("{}通知失败", notify.name), format!("[{label}] {e}")));
+            }
+        }
+    }
+    lines
+}
+
+/// 每个账户一行的markdown表格
+pub fn summary(report: &RunReport) -> String {
+    let title = if report.dry_run { "小米运动同步（dry-run）" } else { "小米运动同步" };
+    let mut text = format!("### {title}\n\n| 账户 | 结果 | 步数 | 失败步骤 | 原因 | 耗时 | 通知 |\n| --- | --- | --- | --- | --- | --- | --- |\n");
+    for account in &report.accounts {
+        let label = match &account.name {
+            Some(name) => format!("{name}（{}）", account.id),
+            None => account.id.clone(),
+        };
+        let status = match account.status {
+            Status::Success => "✅ 成功",
+            Status::Failed => "❌ 失败",
+        };
+        let notify: Vec<String> = account.notify.iter()
+            .map(|notify| format!("{}{}", notify.name, if notify.delivered { "✅" } else { "⚠️" }))
+            .collect();
+        let cells = [
+            label,
+            status.to_string(),
+            account.steps.map(|steps| steps.to_string()).unwrap_or_default(),
+            account.failed_stage.map(|stage| stage.to_string()).unwrap_or_default(),
+            account.error.clone().unwrap_or_default(),
+            format!("{}ms", account.duration_ms),
+            notify.join(" "),
+        ];
+        let cells: Vec<String> = cells.iter().map(|cell| escape_cell(cell)).collect();
+        text.push_str(&format!("| {} |\n", cells.join(" | ")));
+    }
+    text
+}
+
+/// 追加到 `$GITHUB_STEP_SUMMARY` 并输出注解
+pub fn publish(report: &RunReport) -> Result<(), Box<dyn Error>> {
+    write_annotations(&mut std::io::stderr().lock(), report)?;
+    let Ok(path) = env::var("GITHUB_STEP_SUMMARY") else {
+        return Ok(());
+    };
+    OpenOptions::new().create(true).append(true).open(&path)
+        .and_then(|mut file| writeln!(file, "{}", summary(report)))
+        .map_err(|e| format!("写入任务摘要{path}失败：{e}").into())
+}
+
+fn write_annotations(out: &mut impl Write, report: &RunReport) -> std::io::Result<()> {
+    for line in annotations(report) {
+        writeln!(out, "{line}")?;
+    }
+    Ok(())
+}
+
+fn escape_data(value: &str) -> String {
+    value.replace('%', "%25").replace('\r', "%0D").replace('\n', "%0A")
+}
+
+fn escape_property(value: &str) -> String {
+    escape_data(value).replace(':', "%3A").replace(',', "%2C")
+}
+
+fn escape_cell(value: &str) -> String {
+    value.replace('|', "\\|").replace('\n', "<br>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mods::config::EnvOverrides;
+    use crate::mods::mi::Stage;
+    use crate::mods::report::{AccountReport, NotifyReport};
+
+    fn report() -> RunReport {
+        let account = |id: &str, name: Option<&str>, error: Option<&str>, notify_error: Option<&str>| AccountReport {
+            id: id.to_string(),
+            name: name.map(str::to_string),
+            status: if error.is_some() { Status::Failed } else { Status::Success },
+            steps: Some(8000),
+            failed_stage: error.map(|_| Stage::AppToken),
+            error_kind: None,
+            error: error.map(str::to_string),
+            attempts: 1,
+            duration_ms: 1500,
+            stages: Vec::new(),
+            notify: vec![NotifyReport {
+                name: "bark".to_string(),
+                delivered: notify_error.is_none(),
+                error: notify_error.map(str::to_string),
+                duration_ms: 100,
+                targets: Vec::new(),
+            }],
+        };
+        RunReport {
+            schema_version: 1,
+            command: "sync".to_string(),
+            dry_run: false,
+            started_at: "2025-03-01T08:00:00Z".parse().unwrap(),
+            duration_ms: 3000,
+            success: false,
+            accounts: vec![
+                account("138****8000", Some("手机"), None, Some("timeout")),
+                account("s***@example.com", None, Some("获取app_token失败：错误码：0117\n100%"), None),
+            ],
+        }
+    }
+
+    #[test]
+    fn annotations_for_failures() {
+        assert_eq!(annotations(&report()), [
+            "::warning title=bark通知失败::[手机] timeout",
+            "::error title=小米运动同步失败::[s***@example.com] 获取app_token失败：错误码：0117%0A100%25",
+        ]);
+    }
+
+    #[test]
+    fn writes_workflow_commands() {
+        let content = "[[account]]\nuser = \"13800138000\"\npassword = \"p\"\n";
+        let config = Config::parse(content, std::path::Path::new("config.toml"), EnvOverrides::default(), Vec::new()).unwrap();
+        let mut out = Vec::new();
+        write_masks(&mut out, &config).unwrap();
+        write_annotations(&mut out, &report()).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "::add-mask::13800138000\n\
+            ::warning title=bark通知失败::[手机] timeout\n\
+            ::error title=小米运动同步失败::[s***@example.com] 获取app_token失败：错误码：0117%0A100%25\n");
+    }
+
+    #[test]
+    fn summary_table() {
+        assert_eq!(summary(&report()), "### 小米运动同步\n\n\
+            | 账户 | 结果 | 步数 | 失败步骤 | 原因 | 耗时 | 通知 |\n\
+            | --- | --- | --- | --- | --- | --- | --- |\n\
+            | 手机（138****8000） | ✅ 成功 | 8000 |  |  | 1500ms | bark⚠️ |\n\
+            | s***@example.com | ❌ 失败 | 8000 | 获取app_token | 获取app_token失败：错误码：0117<br>100% | 1500ms | bark✅ |\n");
+    }
+}
@@ -0,0 +1,279 @@
+use std::error::Error;
+use std::path::Path;
+use std::sync::Mutex;
+use chrono::{DateTime, NaiveDate, Utc};
+use rusqlite::{params, Connection, Row};
+use serde::{Deserialize, Serialize};
+use crate::mods::mi::StageRecord;
+use crate::mods::report::mask_id;
+
+pub const DEFAULT_HISTORY_FILE: &str = "history.db";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS sync_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user TEXT NOT NULL,
+    account TEXT NOT NULL,
+    date TEXT NOT NULL,
+    started_at TEXT NOT NULL,
+    steps INTEGER,
+    strategy TEXT,
+    success INTEGER NOT NULL,
+    error TEXT,
+    duration_ms INTEGER NOT NULL,
+    stages TEXT NOT NULL,
+    notify TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS sync_history_user_date ON sync_history (user, date);
+";
+
+/// 一次同步的记录，date为账户时区下的日期
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct SyncRecord {
+    pub user: String,
+    pub account: String,
+    pub date: NaiveDate,
+    pub started_at: DateTime<Utc>,
+    pub steps: Option<u32>,
+    pub strategy: Option<String>,
+    pub success: bool,
+    pub error: Option<String>,
+    pub duration_ms: u64,
+    pub stages: Vec<StageRecord>,
+    pub notify: Vec<NotifyRecord>,
+}
+
+impl SyncRecord {
+    /// 与运行报告一样隐藏账号，包括错误信息中的账号，通过HTTP接口返回时使用
+    pub fn masked(&self) -> SyncRecord {
+        let id = mask_id(&self.user);
+        let hide = |e: &String| e.replace(&self.user, &id);
+        SyncRecord {
+            user: id.clone(),
+            account: if self.account == self.user { id.clone() } else { self.account.clone() },
+            error: self.error.as_ref().map(hide),
+            stages: self.stages.iter()
+                .map(|stage| StageRecord { error: stage.error.as_ref().map(hide), ..stage.clone() })
+                .collect(),
+            notify: self.notify.iter()
+                .map(|notify| NotifyRecord { error: notify.error.as_ref().map(hide), ..notify.clone() })
+                .collect(),
+            ..self.clone()
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct NotifyRecord {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub duration_ms: u64,
+    /// 发送到多个目标时每个目标的结果，如bark的每个设备
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<TargetRecord>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TargetRecord {
+    /// 隐藏了密钥的目标，如 `abcd***`
+    pub target: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Default)]
+pub struct HistoryQuery<'a> {
+    /// 账号或显示名称
+    pub account: Option<&'a str>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub limit: Option<usize>,
+}
+
+/// 保存在本地SQLite数据库中的同步记录
+pub struct History {
+    connection: Mutex<Connection>,
+}
+
+impl History {
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let connection = Connection::open(path)
+            .map_err(|e| format!("打开历史记录{}失败：{e}", path.display()))?;
+        Self::init(connection)
+    }
+
+    /// 不读写任何文件，dry-run时使用
+    pub fn in_memory() -> Result<Self, Box<dyn Error>> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(connection: Connection) -> Result<Self, Box<dyn Error>> {
+        connection.execute_batch(SCHEMA)?;
+        Ok(History {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    pub fn record(&self, record: &SyncRecord) -> Result<(), Box<dyn Error>> {
+        self.connection.lock().unwrap().execute(
+            "INSERT INTO sync_history (user, account, date, started_at, steps, strategy, success, error, duration_ms, stages, notify)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                record.user,
+                record.account,
+                record.date.to_string(),
+                record.started_at.to_rfc3339(),
+                record.steps,
+                record.strategy,
+                record.success,
+                record.error,
+                record.duration_ms,
+                serde_json::to_string(&record.stages)?,
+                serde_json::to_string(&record.notify)?,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// 账户最近一次同步成功的时间
+    pub fn last_success(&self, user: &str) -> Result<Option<DateTime<Utc>>, Box<dyn Error>> {
+        let value: Option<String> = self.connection.lock().unwrap().query_row(
+            "SELECT MAX(started_at) FROM sync_history WHERE user = ?1 AND success = 1",
+            params![user],
+            |row| row.get(0),
+        )?;
+        Ok(value.map(|value| value.parse()).transpose()?)
+    }
+
+    /// 按时间倒序返回记录，from和to都包含在内
+    pub fn query(&self, query: &HistoryQuery) -> Result<Vec<SyncRecord>, Box<dyn Error>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT user, account, date, started_at, steps, strategy, success, error, duration_ms, stages, notify
+             FROM sync_history
+             WHERE (?1 IS NULL OR user = ?1 OR account = ?1)
+               AND (?2 IS NULL OR date >= ?2)
+               AND (?3 IS NULL OR date <= ?3)
+             ORDER BY started_at DESC, id DESC
+             LIMIT ?4",
+        )?;
+        let limit = query.limit.map(|limit| limit as i64).unwrap_or(-1);
+        let rows = statement.query_map(
+            params![query.account, query.from.map(|date| date.to_string()), query.to.map(|date| date.to_string()), limit],
+            read_record,
+        )?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+}
+
+fn read_record(row: &Row) -> rusqlite::Result<SyncRecord> {
+    fn parse<T: std::str::FromStr>(index: usize, value: String) -> rusqlite::Result<T> where T::Err: Error + Send + Sync + 'static {
+        value.parse().map_err(|e| rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, Box::new(e)))
+    }
+    fn json<T: serde::de::DeserializeOwned>(index: usize, value: String) -> rusqlite::Result<T> {
+        serde_json::from_str(&value).map_err(|e| rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, Box::new(e)))
+    }
+    Ok(SyncRecord {
+        user: row.get(0)?,
+        account: row.get(1)?,
+        date: parse(2, row.get(2)?)?,
+        started_at: parse(3, row.get(3)?)?,
+        steps: row.get(4)?,
+        strategy: row.get(5)?,
+        success: row.get(6)?,
+        error: row.get(7)?,
+        duration_ms: row.get(8)?,
+        stages: json(9, row.get(9)?)?,
+        notify: json(10, row.get(10)?)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mods::mi::Stage;
+
+    fn record(user: &str, date: &str, steps: u32, success: bool) -> SyncRecord {
+        SyncRecord {
+            user: user.to_string(),
+            account: format!("{user}的手机"),
+            date: date.parse().unwrap(),
+            started_at: format!("{date}T08:00:00Z").parse().unwrap(),
+            steps: Some(steps),
+            strategy: Some("linear".to_string()),
+            success,
+            error: (!success).then(|| "上传步数失败：返回码：0".to_string()),
+            duration_ms: 1200,
+            stages: vec![StageRecord {
+                stage: Stage::Login,
+                status: Some(303),
+                duration_ms: 300,
+                error: None,
+            }],
+            notify: vec![NotifyRecord {
+                name: "bark".to_string(),
+                error: (!success).then(|| "efgh***：timeout".to_string()),
+                duration_ms: 150,
+                targets: vec![
+                    TargetRecord { target: "abcd***".to_string(), error: None },
+                    TargetRecord { target: "efgh***".to_string(), error: (!success).then(|| "timeout".to_string()) },
+                ],
+            }],
+        }
+    }
+
+    #[test]
+    fn records_round_trip() {
+        let history = History::in_memory().unwrap();
+        let expected = record("u1", "2025-03-01", 8000, false);
+        history.record(&expected).unwrap();
+        assert_eq!(history.query(&HistoryQuery::default()).unwrap(), vec![expected]);
+    }
+
+    #[test]
+    fn masked_hides_user() {
+        let raw = SyncRecord {
+            account: "13800138000".to_string(),
+            error: Some("13800138000登录失败".to_string()),
+            ..record("13800138000", "2025-03-01", 1000, false)
+        };
+        let masked = raw.masked();
+        assert_eq!((masked.user.as_str(), masked.account.as_str()), ("138****8000", "138****8000"));
+        assert_eq!(masked.error.as_deref(), Some("138****8000登录失败"));
+        assert!(!serde_json::to_string(&masked).unwrap().contains("13800138000"));
+        assert_eq!(record("13800138000", "2025-03-01", 1000, true).masked().account, "13800138000的手机");
+    }
+
+    #[test]
+    fn last_success_ignores_failures() {
+        let history = History::in_memory().unwrap();
+        assert_eq!(history.last_success("u1").unwrap(), None);
+        history.record(&record("u1", "2025-03-01", 1000, true)).unwrap();
+        history.record(&record("u1", "2025-03-02", 1000, false)).unwrap();
+        history.record(&record("u2", "2025-03-03", 1000, true)).unwrap();
+        assert_eq!(history.last_success("u1").unwrap(), "2025-03-01T08:00:00Z".parse().ok());
+    }
+
+    #[test]
+    fn filters_by_account_and_date_range() {
+        let history = History::in_memory().unwrap();
+        for (user, date) in [("u1", "2025-03-01"), ("u1", "2025-03-02"), ("u2", "2025-03-02"), ("u1", "2025-03-03")] {
+            history.record(&record(user, date, 1000, true)).unwrap();
+        }
+        let dates = |query: HistoryQuery| -> Vec<String> {
+            history.query(&query).unwrap().iter().map(|record| format!("{} {}", record.user, record.date)).collect()
+        };
+
+        assert_eq!(dates(HistoryQuery { account: Some("u1"), ..HistoryQuery::default() }),
+                   ["u1 2025-03-03", "u1 2025-03-02", "u1 2025-03-01"]);
+        assert_eq!(dates(HistoryQuery { account: Some("u2的手机"), ..HistoryQuery::default() }), ["u2 2025-03-02"]);
+        assert_eq!(dates(HistoryQuery {
+            from: "2025-03-02".parse().ok(),
+            to: "2025-03-02".parse().ok(),
+            ..HistoryQuery::default()
+        }), ["u2 2025-03-02", "u1 2025-03-02"]);
+        assert_eq!(dates(HistoryQuery { limit: Some(1), ..HistoryQuery::default() }), ["u1 2025-03-03"]);
+    }
+}
@@ -0,0 +1,10 @@
+//! 小米运动步数同步
+//!
+//! 其他程序可以依赖 [`report`] 中的类型解析 `--output json` 的运行报告，字段的变化见 [`report::SCHEMA_VERSION`]。
+//! `mods` 中的其他模块只供命令行程序使用，不保证稳定。
+
+#[doc(hidden)]
+#[path = "mod.rs"]
+pub mod mods;
+
+pub use mods::report;
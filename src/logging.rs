@@ -0,0 +1,186 @@
+use std::io::{self, IsTerminal, Write};
+use std::sync::RwLock;
+use lazy_static::lazy_static;
+use regex::Regex;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::EnvFilter;
+use crate::mods::cli::LogFormat;
+use crate::mods::dry_run;
+
+lazy_static! {
+    static ref SECRETS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+    /// 表单、URL和JSON中的敏感字段，即使没有登记也会被隐藏
+    static ref SECRET_FIELDS: Regex = Regex::new(
+        r#"(?i)\b(password|access|code|login_token|app_token|apptoken|token)(=|"?\s*:\s*"?)([^&\s",}]+)"#
+    ).unwrap();
+}
+
+/// 登记需要在日志中隐藏的值，如密码和运行中获取的token
+pub fn add_secret(secret: &str) {
+    if secret.is_empty() {
+        return;
+    }
+    let mut secrets = SECRETS.write().unwrap();
+    for form in escaped_forms(secret) {
+        if !secrets.contains(&form) {
+            secrets.push(form);
+        }
+    }
+    // 先替换长的，避免较短的值是较长的值的一部分时只隐藏了一半
+    secrets.sort_by_key(|known| std::cmp::Reverse(known.len()));
+}
+
+/// 原值以及JSON日志和字段的Debug输出中转义后的样子，含有 `"` 或 `\` 的密码转义后才能匹配
+fn escaped_forms(secret: &str) -> Vec<String> {
+    let unquote = |quoted: String| quoted[1..quoted.len() - 1].to_string();
+    let mut forms = vec![secret.to_string()];
+    for form in [unquote(serde_json::to_string(secret).unwrap()), unquote(format!("{secret:?}"))] {
+        if !forms.contains(&form) {
+            forms.push(form);
+        }
+    }
+    forms
+}
+
+pub fn redact(text: &str) -> String {
+    // 敏感字段的规则遇到 `"` 就停止，先按规则替换的话含有 `"` 的密码只会隐藏前一半，剩下的部分也无法再匹配登记的值
+    let mut text = text.to_string();
+    for secret in SECRETS.read().unwrap().iter() {
+        text = text.replace(secret.as_str(), &dry_run::mask(secret));
+    }
+    SECRET_FIELDS.replace_all(&text, |captures: &regex::Captures| {
+        format!("{}{}{}", &captures[1], &captures[2], dry_run::mask(&captures[3]))
+    }).into_owned()
+}
+
+/// 写入前隐藏敏感信息，所有日志都经过这里，debug级别也不例外
+pub struct RedactingWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write_all(redact(&String::from_utf8_lossy(buf)).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+pub struct Redacting<M> {
+    inner: M,
+}
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for Redacting<M> {
+    type Writer = RedactingWriter<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter {
+            inner: self.inner.make_writer(),
+        }
+    }
+}
+
+pub fn redacting<M>(inner: M) -> Redacting<M> {
+    Redacting {
+        inner,
+    }
+}
+
+/// 日志输出到标准错误，level为 `info`、`mi_steps=debug` 这样的过滤规则
+pub fn init(format: LogFormat, level: &str) -> Result<(), String> {
+    let filter = EnvFilter::try_new(level).map_err(|e| format!("日志级别错误：{level}（{e}）"))?;
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_ansi(io::stderr().is_terminal())
+        .with_writer(redacting(io::stderr));
+    let result = match format {
+        LogFormat::Pretty => builder.try_init(),
+        LogFormat::Json => builder.json().with_current_span(true).with_span_list(true).try_init(),
+    };
+    result.map_err(|e| format!("初始化日志失败：{e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Buffer {
+        type Writer = Buffer;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    #[test]
+    fn hides_fields_in_forms_urls_and_json() {
+        assert_eq!(redact("POST https://x/tokens password=hunter2&token=access"), "POST https://x/tokens password=******&token=******");
+        assert_eq!(redact("GET https://x/app_tokens?app_name=a&login_token=abc.def"), "GET https://x/app_tokens?app_name=a&login_token=******");
+        assert_eq!(redact(r#"{"token_info":{"login_token":"abc","user_id":"42"}}"#), r#"{"token_info":{"login_token":"******","user_id":"42"}}"#);
+        assert_eq!(redact("apptoken: xyz"), "apptoken: ******");
+    }
+
+    #[test]
+    fn hides_registered_secrets_at_debug_level() {
+        add_secret("s3cr3t-pass");
+        add_secret("ZZTOKENZZ");
+        let buffer = Buffer::default();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_span_events(FmtSpan::CLOSE)
+            .with_writer(redacting(buffer.clone()))
+            .json()
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("account", account = "u1", secret = "ZZTOKENZZ");
+            let _entered = span.enter();
+            tracing::debug!(body = "user=u1 pass s3cr3t-pass", "请求");
+            tracing::error!("登录失败：s3cr3t-pass ZZTOKENZZ");
+        });
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert!(output.contains("请求") && output.contains("u1"), "{output}");
+        assert!(!output.contains("s3cr3t-pass"), "{output}");
+        assert!(!output.contains("ZZTOKENZZ"), "{output}");
+    }
+
+    #[test]
+    fn hides_escaped_secrets_in_json() {
+        let password = r#"pa"ss\w0rd"#;
+        add_secret(password);
+        for format in [LogFormat::Json, LogFormat::Pretty] {
+            let buffer = Buffer::default();
+            let builder = tracing_subscriber::fmt().with_writer(redacting(buffer.clone()));
+            let dispatch = match format {
+                LogFormat::Json => tracing::Dispatch::new(builder.json().finish()),
+                LogFormat::Pretty => tracing::Dispatch::new(builder.finish()),
+            };
+            tracing::dispatcher::with_default(&dispatch, || {
+                tracing::error!(password, "登录失败：{password}");
+            });
+            let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+            assert!(output.contains("登录失败"), "{output}");
+            assert!(!output.contains("pa\\\"ss") && !output.contains("ss\\\\w0rd"), "{output}");
+            assert!(!output.contains(password), "{output}");
+        }
+    }
+}
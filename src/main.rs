@@ -1,48 +1,179 @@
-#[path = "mod.rs"]
-mod mods;
+use std::error::Error;
+use std::process::ExitCode;
+use std::sync::Arc;
+use clap::Parser;
+use serde_json::json;
+use mi_steps::mods::api::{self, DEFAULT_API_LISTEN};
+use mi_steps::mods::cli::{Cli, Command, ConfigCommand, Output};
+use mi_steps::mods::config::{self, Config};
+use mi_steps::mods::daemon;
+use mi_steps::mods::github;
+use mi_steps::mods::history::{HistoryQuery, SyncRecord};
+use mi_steps::mods::logging;
+use mi_steps::mods::report::RunReport;
+use mi_steps::mods::runner::{parse_time, AccountResult, Runner};
 
-use std::env;
-use chrono::{Datelike, Local, TimeZone};
-use crate::mods::bark::{Bark, BarkMessage};
-
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let accounts = env::var("ACCOUNTS")?;
-    let bark_server = env::var("BARK_SERVER")?;
-    let bark_key = env::var("BARK_KEY")?;
-    let full_time = env::var("FULL_TIME").unwrap_or("17".to_string()).parse::<u32>()?;
-    let max_steps = env::var("MAX_STEPS").unwrap_or("100000".to_string()).parse::<u32>()?;
+fn main() -> ExitCode {
+    // .env中的LOG_LEVEL等也对命令行参数生效，需要在启动运行时的线程之前设置环境变量
+    if let Err(e) = config::load_dotenv() {
+        eprintln!("{e}");
+        return ExitCode::FAILURE;
+    }
+    let cli = Cli::parse();
+    if let Err(e) = logging::init(cli.log_format, &cli.log_level) {
+        eprintln!("{e}");
+        return ExitCode::FAILURE;
+    }
+    let runtime = match tokio::runtime::Builder::new_multi_thread().enable_all().build() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("启动运行时失败：{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match runtime.block_on(run(&cli)) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            match cli.output {
+                Output::Text => eprintln!("{e}"),
+                Output::Json => println!("{}", json!({ "success": false, "error": e.to_string() })),
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
 
-    let bark = if !(bark_key.is_empty() || bark_server.is_empty()) {
-        Some(Bark::new(bark_server, bark_key))
-    } else { None };
+async fn run(cli: &Cli) -> Result<bool, Box<dyn Error>> {
+    let started_at = chrono::Utc::now();
+    let config = Config::load(cli.config.as_deref())?;
+    for (name, source) in config::sources() {
+        eprintln!("{name} 来自{source}");
+    }
+    if github::enabled() {
+        github::add_masks(&config);
+    }
+    let runner = Runner::new(config, cli.dry_run)?;
+    let command = cli.command.as_ref().unwrap_or(&Command::Sync { account: None });
+    let results = match command {
+        Command::Sync { account } => runner.sync(account.as_deref(), None).await?,
+        Command::Set { steps, account } => runner.sync(account.as_deref(), Some(*steps)).await?,
+        Command::LoginCheck { account } => runner.login_check(account.as_deref()).await?,
+        Command::Preview { account, at } => {
+            let at = at.as_deref().map(parse_time).transpose()?;
+            runner.preview(account.as_deref(), at)?
+        }
+        Command::Daemon => {
+            daemon::run(&runner).await?;
+            return Ok(true);
+        }
+        Command::Serve { listen } => {
+            let listen = listen.or(runner.config.api_listen).unwrap_or_else(|| DEFAULT_API_LISTEN.parse().unwrap());
+            api::serve(Arc::new(runner), listen).await?;
+            return Ok(true);
+        }
+        Command::History { account, from, to, limit } => {
+            let records = runner.history.query(&HistoryQuery {
+                account: account.as_deref(),
+                from: *from,
+                to: *to,
+                limit: Some(*limit),
+            })?;
+            print_history(cli, &records)?;
+            return Ok(true);
+        }
+        Command::Config { command: ConfigCommand::Validate } => {
+            let enabled = runner.config.accounts.iter().filter(|account| account.enabled).count();
+            match cli.output {
+                Output::Text => println!("配置检查通过：共{}个账户，{enabled}个启用", runner.config.accounts.len()),
+                Output::Json => println!("{}", json!({ "success": true, "accounts": runner.config.accounts.len(), "enabled": enabled })),
+            }
+            return Ok(true);
+        }
+    };
+    let synced = matches!(command, Command::Sync { .. } | Command::Set { .. });
+    if let (true, false, Some(path)) = (synced, cli.dry_run, &runner.config.metrics_file) {
+        runner.metrics.write_textfile(path)?;
+    }
+    let name = match command {
+        Command::Sync { .. } => "sync",
+        Command::Set { .. } => "set",
+        Command::LoginCheck { .. } => "login_check",
+        _ => "preview",
+    };
+    let report = RunReport::new(name, cli.dry_run, started_at, &results);
+    if github::enabled() {
+        github::publish(&report)?;
+    }
+    print_results(cli, &results, &report, synced)?;
+    Ok(report.success)
+}
 
-    for account in accounts.lines() {
-        let (account, password) = account.split_once("#").unwrap();
-        let steps = gen_steps(full_time, max_steps);
-        mods::mi::update_steps(account, password, steps).await?;
-        if let Some(bark) = &bark {
-            let mut message = BarkMessage::default();
-            message.body = format!("🏃‍[{}]更新步数成功->{}。", account, steps);
-            message.title = Some("✔小米运动同步🐾".to_string());
-            message.group = Some("小米运动同步记录".to_string());
-            bark.send_message(message).await?;
-        }
-    }
-    println!("同步成功!!!!!!!!!!!!!!!");
+fn print_results(cli: &Cli, results: &[AccountResult], report: &RunReport, synced: bool) -> Result<(), Box<dyn Error>> {
+    if let Some(path) = &cli.output_file {
+        std::fs::write(path, serde_json::to_string_pretty(report)?)
+            .map_err(|e| format!("写入报告{}失败：{e}", path.display()))?;
+    }
+    match cli.output {
+        Output::Json if cli.output_file.is_some() => {}
+        Output::Json => println!("{}", serde_json::to_string_pretty(report)?),
+        Output::Text => {
+            let prefix = if cli.dry_run { "[dry-run]" } else { "" };
+            for result in results {
+                let steps = result.steps.map(|steps| format!(" 步数：{steps}")).unwrap_or_default();
+                match &result.error {
+                    None => println!("{prefix}✔[{}]{steps}", result.account),
+                    Some(e) => println!("{prefix}✘[{}]{steps} 失败：{e}", result.account),
+                }
+                for e in result.notify_errors() {
+                    println!("{prefix}⚠[{}] {e}", result.account);
+                }
+            }
+            if synced && results.iter().all(|result| result.success) {
+                println!("同步成功!!!!!!!!!!!!!!!");
+            }
+        }
+    }
     Ok(())
 }
 
-
-fn gen_steps(full_time: u32, max_steps: u32) -> u32 {
-    let now = chrono::Local::now();
-    let mut millis = now.timestamp_millis();
-    millis = millis - Local.with_ymd_and_hms(now.year(), now.month(), now.day(), 0, 0, 0).unwrap().timestamp_millis();
-    let all_millis = full_time * 3600000;
-
-    return if millis < all_millis as i64 {
-        let rate = millis as f64 / all_millis as f64;
-        let step = rate * max_steps as f64;
-        step as u32
-    } else { max_steps };
+fn print_history(cli: &Cli, records: &[SyncRecord]) -> Result<(), Box<dyn Error>> {
+    match cli.output {
+        Output::Json => println!("{}", serde_json::to_string_pretty(records)?),
+        Output::Text => {
+            if records.is_empty() {
+                println!("没有同步记录");
+            }
+            for record in records {
+                let started_at = record.started_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S");
+                let steps = record.steps.map(|steps| format!(" 步数：{steps}")).unwrap_or_default();
+                let strategy = record.strategy.as_deref().unwrap_or("指定步数");
+                let stages: Vec<String> = record.stages.iter()
+                    .map(|stage| match stage.status {
+                        Some(status) => format!("{}({status},{}ms)", stage.stage, stage.duration_ms),
+                        None => format!("{}({}ms)", stage.stage, stage.duration_ms),
+                    })
+                    .collect();
+                let mark = if record.success { "✔" } else { "✘" };
+                println!("{started_at} {mark}[{}]{steps} 策略：{strategy} 耗时：{}ms 步骤：{}",
+                         record.account, record.duration_ms, stages.join(" → "));
+                if let Some(e) = &record.error {
+                    println!("    失败：{e}");
+                }
+                for notify in &record.notify {
+                    match &notify.error {
+                        Some(e) => println!("    ⚠{}通知失败：{e}", notify.name),
+                        None => println!("    {}通知成功", notify.name),
+                    }
+                    for target in &notify.targets {
+                        match &target.error {
+                            Some(e) => println!("        ✘{} {e}", target.target),
+                            None => println!("        ✔{}", target.target),
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
 }
@@ -0,0 +1,174 @@
+use std::error::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder, StatusCode, Url};
+use serde_json::{json, Value};
+use crate::mods::dry_run;
+use crate::mods::notify::{escape_html, Notification, Notifier};
+
+const MAX_ATTEMPTS: u32 = 3;
+
+static TXN_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+pub struct Matrix {
+    homeserver: String,
+    access_token: String,
+    room_id: String,
+    client: Client,
+    /// 第n次重试前等待n倍的时间
+    retry_delay: Duration,
+}
+
+impl Matrix {
+    pub fn new(homeserver: String, access_token: String, room_id: String) -> Result<Self, Box<dyn Error>> {
+        Ok(Matrix {
+            homeserver,
+            access_token,
+            room_id,
+            client: Client::builder().build()?,
+            retry_delay: Duration::from_secs(1),
+        })
+    }
+
+    pub async fn send_message(&self, body: &str, formatted_body: &str) -> Result<(), Box<dyn Error>> {
+        // 同一条消息的重试复用同一个txn_id，服务端据此去重
+        let txn_id = Self::gen_txn_id();
+        let url = Self::build_url(&self.homeserver, &self.room_id, &txn_id)?;
+        let content = Self::build_content(body, formatted_body);
+
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let resp = self.build_request(&url, &content).send().await;
+            let retry_err = match resp {
+                Ok(resp) => {
+                    let status = resp.status();
+                    if status.is_success() {
+                        return Ok(());
+                    }
+                    let json_value = resp.json::<Value>().await.unwrap_or_default();
+                    let err = format!("matrix同步失败：{} {}", status.as_u16(), json_value["errcode"].as_str().unwrap_or_default());
+                    if !(status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS) {
+                        return Err(err.into());
+                    }
+                    err
+                }
+                Err(e) => format!("matrix同步失败：{e}"),
+            };
+            if attempt >= MAX_ATTEMPTS {
+                return Err(retry_err.into());
+            }
+            tokio::time::sleep(self.retry_delay * attempt).await;
+        }
+    }
+
+    fn gen_txn_id() -> String {
+        let millis = chrono::Utc::now().timestamp_millis();
+        let seq = TXN_COUNTER.fetch_add(1, Ordering::Relaxed);
+        format!("mi_steps.{millis}.{seq}")
+    }
+
+    fn build_url(homeserver: &str, room_id: &str, txn_id: &str) -> Result<String, Box<dyn Error>> {
+        let mut url = Url::parse(homeserver)?;
+        url.path_segments_mut()
+            .map_err(|_| "matrix服务器地址错误")?
+            .pop_if_empty()
+            .extend(["_matrix", "client", "v3", "rooms", room_id, "send", "m.room.message", txn_id]);
+        Ok(url.as_str().to_string())
+    }
+
+    fn build_request(&self, url: &str, content: &Value) -> RequestBuilder {
+        self.client.put(url)
+            .bearer_auth(&self.access_token)
+            .json(content)
+    }
+
+    fn build_content(body: &str, formatted_body: &str) -> Value {
+        json!({
+            "msgtype": "m.text",
+            "body": body,
+            "format": "org.matrix.custom.html",
+            "formatted_body": formatted_body,
+        })
+    }
+
+    fn format_plain(notification: &Notification) -> String {
+        format!("{}\n{}", notification.title, notification.body)
+    }
+
+    fn format_html(notification: &Notification) -> String {
+        format!("<b>{}</b><br>{}", escape_html(&notification.title), escape_html(&notification.body))
+    }
+}
+
+#[async_trait]
+impl Notifier for Matrix {
+    fn name(&self) -> &str {
+        "matrix"
+    }
+
+    async fn notify(&self, notification: &Notification) -> Result<(), Box<dyn Error>> {
+        self.send_message(&Self::format_plain(notification), &Self::format_html(notification)).await
+    }
+
+    fn preview(&self, notification: &Notification) -> Result<Vec<String>, Box<dyn Error>> {
+        let url = Self::build_url(&self.homeserver, &self.room_id, &Self::gen_txn_id())?;
+        let content = Self::build_content(&Self::format_plain(notification), &Self::format_html(notification));
+        Ok(vec![format!("matrix {}", dry_run::describe(&self.build_request(&url, &content).build()?, &[]))])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use axum::extract::{Request, State};
+    use axum::http::StatusCode as HttpStatus;
+    use axum::Router;
+
+    #[test]
+    fn build_url_escapes_room_and_keeps_path() {
+        assert_eq!(Matrix::build_url("https://matrix.org", "!abc:matrix.org", "t1").unwrap(),
+                   "https://matrix.org/_matrix/client/v3/rooms/!abc:matrix.org/send/m.room.message/t1");
+        assert_eq!(Matrix::build_url("https://example.com/synapse/", "#room:example.com", "t1").unwrap(),
+                   "https://example.com/synapse/_matrix/client/v3/rooms/%23room:example.com/send/m.room.message/t1");
+    }
+
+    #[test]
+    fn plain_and_html_body() {
+        let notification = Notification { title: "<同步>".to_string(), body: "a & b".to_string(), group: None };
+        let content = Matrix::build_content(&Matrix::format_plain(&notification), &Matrix::format_html(&notification));
+        assert_eq!(content, json!({
+            "msgtype": "m.text",
+            "body": "<同步>\na & b",
+            "format": "org.matrix.custom.html",
+            "formatted_body": "<b>&lt;同步&gt;</b><br>a &amp; b",
+        }));
+    }
+
+    #[tokio::test]
+    async fn retries_reuse_txn_id() {
+        let paths = Arc::new(Mutex::new(Vec::new()));
+        let app = Router::new()
+            .fallback(|State(paths): State<Arc<Mutex<Vec<String>>>>, request: Request| async move {
+                let mut paths = paths.lock().unwrap();
+                paths.push(request.uri().path().to_string());
+                if paths.len() < 3 { HttpStatus::BAD_GATEWAY } else { HttpStatus::OK }
+            })
+            .with_state(paths.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let homeserver = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let matrix = Matrix {
+            retry_delay: Duration::ZERO,
+            ..Matrix::new(homeserver, "token".to_string(), "!room:example.com".to_string()).unwrap()
+        };
+        matrix.send_message("body", "<b>body</b>").await.unwrap();
+        let paths = paths.lock().unwrap();
+        assert_eq!(paths.len(), 3);
+        assert!(paths[0].starts_with("/_matrix/client/v3/rooms/!room:example.com/send/m.room.message/mi_steps."), "{paths:?}");
+        assert!(paths.iter().all(|path| path == &paths[0]), "{paths:?}");
+    }
+}
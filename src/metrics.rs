@@ -0,0 +1,211 @@
+use std::error::Error;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use axum::http::header::CONTENT_TYPE;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use chrono::{DateTime, Utc};
+use prometheus::{Encoder, GaugeVec, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry, TextEncoder};
+use crate::mods::history::SyncRecord;
+use crate::mods::report::mask_id;
+
+/// 同步相关的Prometheus指标，标签account为配置的name，没有设置name时为隐藏了中间部分的账号
+pub struct Metrics {
+    registry: Registry,
+    attempts: IntCounterVec,
+    successes: IntCounterVec,
+    failures: IntCounterVec,
+    steps: GaugeVec,
+    last_success: GaugeVec,
+    http_duration: HistogramVec,
+    notify_failures: IntCounterVec,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new_custom(Some("mi_steps".to_string()), None).unwrap();
+        let attempts = IntCounterVec::new(Opts::new("sync_attempts_total", "同步次数"), &["account"]).unwrap();
+        let successes = IntCounterVec::new(Opts::new("sync_success_total", "同步成功次数"), &["account"]).unwrap();
+        let failures = IntCounterVec::new(Opts::new("sync_failures_total", "同步失败次数，stage为失败的步骤"), &["account", "stage"]).unwrap();
+        let steps = GaugeVec::new(Opts::new("steps", "最近一次同步的步数"), &["account"]).unwrap();
+        let last_success = GaugeVec::new(Opts::new("last_success_timestamp_seconds", "最近一次同步成功的时间"), &["account"]).unwrap();
+        let http_duration = HistogramVec::new(
+            HistogramOpts::new("http_request_duration_seconds", "华米接口的请求耗时")
+                .buckets(vec![0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0]),
+            &["endpoint"],
+        ).unwrap();
+        let notify_failures = IntCounterVec::new(Opts::new("notify_failures_total", "通知失败次数"), &["account", "notifier"]).unwrap();
+        registry.register(Box::new(attempts.clone())).unwrap();
+        registry.register(Box::new(successes.clone())).unwrap();
+        registry.register(Box::new(failures.clone())).unwrap();
+        registry.register(Box::new(steps.clone())).unwrap();
+        registry.register(Box::new(last_success.clone())).unwrap();
+        registry.register(Box::new(http_duration.clone())).unwrap();
+        registry.register(Box::new(notify_failures.clone())).unwrap();
+        Metrics {
+            registry,
+            attempts,
+            successes,
+            failures,
+            steps,
+            last_success,
+            http_duration,
+            notify_failures,
+        }
+    }
+
+    pub fn observe(&self, record: &SyncRecord) {
+        let account = label(&record.user, &record.account);
+        let account = account.as_str();
+        self.attempts.with_label_values(&[account]).inc();
+        for stage in &record.stages {
+            self.http_duration.with_label_values(&[stage.stage.as_str()]).observe(stage.duration_ms as f64 / 1000.0);
+        }
+        if record.success {
+            self.successes.with_label_values(&[account]).inc();
+            if let Some(steps) = record.steps {
+                self.steps.with_label_values(&[account]).set(steps as f64);
+            }
+            self.last_success.with_label_values(&[account]).set(record.started_at.timestamp() as f64);
+        } else {
+            let stage = record.stages.last()
+                .filter(|stage| stage.error.is_some())
+                .map_or("unknown", |stage| stage.stage.as_str());
+            self.failures.with_label_values(&[account, stage]).inc();
+        }
+        for notify in record.notify.iter().filter(|notify| notify.error.is_some()) {
+            self.notify_failures.with_label_values(&[account, &notify.name]).inc();
+        }
+    }
+
+    /// 从历史记录中恢复，单次运行失败时也能保留上次成功的时间
+    pub fn set_last_success(&self, user: &str, account: &str, at: DateTime<Utc>) {
+        self.last_success.with_label_values(&[&label(user, account)]).set(at.timestamp() as f64);
+    }
+
+    pub fn render(&self) -> Result<String, Box<dyn Error>> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+
+    /// 写入node_exporter的textfile目录，先写临时文件再改名，避免读到一半的文件
+    pub fn write_textfile(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let temp = path.with_extension("prom.tmp");
+        fs::write(&temp, self.render()?)
+            .and_then(|_| fs::rename(&temp, path))
+            .map_err(|e| format!("写入指标文件{}失败：{e}", path.display()).into())
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `/metrics` 不需要认证，textfile也可能被其他用户读取，所以不能使用原始的账号
+fn label(user: &str, account: &str) -> String {
+    if account == user { mask_id(user) } else { account.to_string() }
+}
+
+/// 在listen地址上提供 `/metrics`，监听成功后在后台运行
+pub async fn spawn(metrics: Arc<Metrics>, listen: SocketAddr) -> Result<(), Box<dyn Error>> {
+    let app = Router::new().route("/metrics", get(move || {
+        let body = metrics.render().map_err(|e| e.to_string());
+        async move {
+            match body {
+                Ok(body) => ([(CONTENT_TYPE, "text/plain; version=0.0.4")], body).into_response(),
+                Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+            }
+        }
+    }));
+    let listener = tokio::net::TcpListener::bind(listen).await
+        .map_err(|e| format!("监听{listen}失败：{e}"))?;
+    tracing::info!(url = %format!("http://{listen}/metrics"), "指标服务已启动");
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            tracing::error!("指标服务已停止：{e}");
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mods::history::NotifyRecord;
+    use crate::mods::mi::{Stage, StageRecord};
+
+    fn record(success: bool) -> SyncRecord {
+        let stage = |stage, error: Option<&str>| StageRecord {
+            stage,
+            status: Some(200),
+            duration_ms: 300,
+            error: error.map(str::to_string),
+        };
+        SyncRecord {
+            user: "13800138000".to_string(),
+            account: "手机".to_string(),
+            date: "2025-03-01".parse().unwrap(),
+            started_at: "2025-03-01T08:00:00Z".parse().unwrap(),
+            steps: Some(8000),
+            strategy: Some("linear".to_string()),
+            success,
+            error: (!success).then(|| "获取app_token失败：错误码：0117".to_string()),
+            duration_ms: 900,
+            stages: if success {
+                vec![stage(Stage::Login, None), stage(Stage::Token, None), stage(Stage::AppToken, None), stage(Stage::Upload, None)]
+            } else {
+                vec![stage(Stage::Login, None), stage(Stage::Token, None), stage(Stage::AppToken, Some("错误码：0117"))]
+            },
+            notify: vec![NotifyRecord { name: "bark".to_string(), error: Some("timeout".to_string()), duration_ms: 5000, targets: Vec::new() }],
+        }
+    }
+
+    #[test]
+    fn renders_success_and_failure() {
+        let metrics = Metrics::new();
+        metrics.observe(&record(true));
+        metrics.observe(&record(false));
+        let text = metrics.render().unwrap();
+        for line in [
+            "mi_steps_sync_attempts_total{account=\"手机\"} 2",
+            "mi_steps_sync_success_total{account=\"手机\"} 1",
+            "mi_steps_sync_failures_total{account=\"手机\",stage=\"app_token\"} 1",
+            "mi_steps_steps{account=\"手机\"} 8000",
+            "mi_steps_last_success_timestamp_seconds{account=\"手机\"} 1740816000",
+            "mi_steps_http_request_duration_seconds_count{endpoint=\"login\"} 2",
+            "mi_steps_http_request_duration_seconds_count{endpoint=\"upload\"} 1",
+            "mi_steps_notify_failures_total{account=\"手机\",notifier=\"bark\"} 2",
+        ] {
+            assert!(text.lines().any(|l| l == line), "缺少 {line}：\n{text}");
+        }
+    }
+
+    #[test]
+    fn masks_user_without_name() {
+        let metrics = Metrics::new();
+        metrics.observe(&SyncRecord { account: "13800138000".to_string(), ..record(true) });
+        metrics.set_last_success("a@b.com", "a@b.com", "2025-03-01T08:00:00Z".parse().unwrap());
+        let text = metrics.render().unwrap();
+        assert!(text.contains("mi_steps_sync_success_total{account=\"138****8000\"} 1"), "{text}");
+        assert!(text.contains("mi_steps_last_success_timestamp_seconds{account=\"a***@b.com\"} 1740816000"), "{text}");
+        assert!(!text.contains("13800138000") && !text.contains("a@b.com"), "{text}");
+    }
+
+    #[test]
+    fn writes_textfile() {
+        let path = std::env::temp_dir().join(format!("mi_steps_{}.prom", std::process::id()));
+        let metrics = Metrics::new();
+        metrics.observe(&record(false));
+        metrics.write_textfile(&path).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(text.contains("mi_steps_sync_failures_total{account=\"手机\",stage=\"app_token\"} 1"), "{text}");
+    }
+}
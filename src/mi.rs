@@ -1,23 +1,205 @@
 use std::error::Error;
+use std::fmt;
+use std::time::Instant;
 use lazy_static::lazy_static;
 use regex::Regex;
 use reqwest::header::{HeaderMap, HeaderValue};
-use reqwest::Url;
+use reqwest::{Client, RequestBuilder, Response, Url};
+use serde::{Deserialize, Serialize};
 use serde_json::{Value};
+use tracing::{debug, instrument, trace};
+use crate::mods::dry_run;
+use crate::mods::logging;
 
 lazy_static! {
         static ref EMAIL_REG : Regex = Regex::new(r"^\w+([-+.]\w+)*@\w+([-.]\w+)*\.\w+([-.]\w+)*$").unwrap();
     }
 
-pub async fn update_steps(account: &str, password: &str, steps: u32) -> Result<(), Box<dyn Error>> {
-    let access = login(account, password).await?;
-    let (login_token, user_id) = get_token(account, access.as_str()).await?;
-    let app_token = get_app_token(login_token.as_str()).await?;
-    sync_steps(app_token.as_str(), user_id.as_str(), steps).await?;
-    Ok(())
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    Login,
+    Token,
+    AppToken,
+    Upload,
 }
 
-async fn login(account: &str, password: &str) -> Result<String, Box<dyn Error>> {
+impl Stage {
+    /// 英文名称，与JSON中的一致
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stage::Login => "login",
+            Stage::Token => "token",
+            Stage::AppToken => "app_token",
+            Stage::Upload => "upload",
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Stage::Login => "登录",
+            Stage::Token => "获取login_token",
+            Stage::AppToken => "获取app_token",
+            Stage::Upload => "上传步数",
+        })
+    }
+}
+
+/// 失败原因的分类
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// 连接失败等网络错误
+    Network,
+    Timeout,
+    /// 登录失败，通常是账号或密码错误
+    Auth,
+    /// 接口返回了错误码或无法解析的内容
+    Api,
+    /// 其他实例正在同步该账户
+    Locked,
+    /// 读写状态存储失败
+    State,
+}
+
+/// 每一步的HTTP状态码、耗时和错误
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct StageRecord {
+    pub stage: Stage,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    pub duration_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// 记录同步过程中执行到的每一步
+#[derive(Default)]
+pub struct Trace {
+    pub stages: Vec<StageRecord>,
+    pub error_kind: Option<ErrorKind>,
+    started: Option<Instant>,
+}
+
+impl Trace {
+    fn start(&mut self, stage: Stage) {
+        self.finish(None);
+        self.stages.push(StageRecord {
+            stage,
+            status: None,
+            duration_ms: 0,
+            error: None,
+        });
+        self.started = Some(Instant::now());
+    }
+
+    fn status(&mut self, status: u16) {
+        if let Some(record) = self.stages.last_mut() {
+            record.status = Some(status);
+        }
+    }
+
+    fn finish(&mut self, error: Option<String>) {
+        if let (Some(started), Some(record)) = (self.started.take(), self.stages.last_mut()) {
+            record.duration_ms = started.elapsed().as_millis() as u64;
+            record.error = error;
+        }
+    }
+
+    /// 失败时所在的步骤
+    pub fn failed_stage(&self) -> Option<Stage> {
+        self.stages.last().filter(|record| record.error.is_some()).map(|record| record.stage)
+    }
+}
+
+/// 登录后得到的凭据，保存在状态存储中，下次同步时跳过登录
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Session {
+    pub user_id: String,
+    pub app_token: String,
+}
+
+/// 所有请求共用的客户端，登录接口通过跳转地址返回access，不能自动跟随跳转
+pub fn client() -> Result<Client, Box<dyn Error>> {
+    Ok(Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?)
+}
+
+/// dry_run为true时只打印每一步的请求，后续步骤使用占位的token
+pub async fn login_check(client: &Client, account: &str, password: &str, dry_run: bool, trace: &mut Trace) -> Result<String, Box<dyn Error>> {
+    let result = async {
+        let access = login(client, account, password, dry_run, trace).await?;
+        let (_, user_id) = get_token(client, account, access.as_str(), dry_run, trace).await?;
+        Ok(user_id)
+    }.await;
+    finish(trace, result)
+}
+
+/// 有缓存的凭据时直接上传，被拒绝后重新登录再上传一次，返回这次使用的凭据
+pub async fn update_steps(client: &Client, account: &str, password: &str, steps: u32, cached: Option<&Session>, dry_run: bool, trace: &mut Trace) -> Result<Session, Box<dyn Error>> {
+    if let Some(session) = cached {
+        logging::add_secret(&session.app_token);
+        match sync_steps(client, &session.app_token, &session.user_id, steps, dry_run, trace).await {
+            Ok(()) => return finish(trace, Ok(session.clone())),
+            // 网络错误时凭据可能仍然有效，不重新登录
+            Err(e) if matches!(error_kind(Some(Stage::Upload), e.as_ref()), ErrorKind::Network | ErrorKind::Timeout) => return finish(trace, Err(e)),
+            Err(e) => {
+                debug!("缓存的app_token被拒绝，重新登录：{e}");
+                trace.finish(Some(e.to_string()));
+            }
+        }
+    }
+    let result = async {
+        let access = login(client, account, password, dry_run, trace).await?;
+        let (login_token, user_id) = get_token(client, account, access.as_str(), dry_run, trace).await?;
+        let app_token = get_app_token(client, login_token.as_str(), dry_run, trace).await?;
+        sync_steps(client, app_token.as_str(), user_id.as_str(), steps, dry_run, trace).await?;
+        Ok(Session { user_id, app_token })
+    }.await;
+    finish(trace, result)
+}
+
+fn finish<T>(trace: &mut Trace, result: Result<T, Box<dyn Error>>) -> Result<T, Box<dyn Error>> {
+    trace.finish(result.as_ref().err().map(|e| e.to_string()));
+    if let Err(e) = &result {
+        trace.error_kind = Some(error_kind(trace.failed_stage(), e.as_ref()));
+    }
+    result.map_err(|e| match trace.failed_stage() {
+        Some(stage) => format!("{stage}失败：{e}").into(),
+        None => e,
+    })
+}
+
+fn error_kind(stage: Option<Stage>, e: &(dyn Error + 'static)) -> ErrorKind {
+    match e.downcast_ref::<reqwest::Error>() {
+        Some(e) if e.is_timeout() => ErrorKind::Timeout,
+        Some(e) if !e.is_decode() => ErrorKind::Network,
+        _ if stage == Some(Stage::Login) => ErrorKind::Auth,
+        _ => ErrorKind::Api,
+    }
+}
+
+async fn send(client: &Client, request: RequestBuilder, dry_run: bool, trace: &mut Trace) -> Result<Option<Response>, Box<dyn Error>> {
+    let request = request.build()?;
+    if dry_run {
+        eprintln!("[dry-run] {}", dry_run::describe(&request, &["password"]));
+        return Ok(None);
+    }
+    let body = request.body().and_then(|body| body.as_bytes()).map(String::from_utf8_lossy).unwrap_or_default();
+    debug!(method = %request.method(), url = %request.url(), headers = ?request.headers(), "发送请求");
+    trace!(%body, "请求内容");
+    let response = client.execute(request).await?;
+    debug!(status = response.status().as_u16(), headers = ?response.headers(), "收到响应");
+    trace.status(response.status().as_u16());
+    Ok(Some(response))
+}
+
+#[instrument(name = "stage", skip_all, fields(stage = "login"))]
+async fn login(client: &Client, account: &str, password: &str, dry_run: bool, trace: &mut Trace) -> Result<String, Box<dyn Error>> {
+    trace.start(Stage::Login);
     let params = [
         ("client_id", "HuaMi"),
         ("redirect_uri", "https://s3-us-west-2.amazonaws.com/hm-registration/successsignin.html"),
@@ -30,19 +212,21 @@ async fn login(account: &str, password: &str) -> Result<String, Box<dyn Error>>
         format!("+86{account}")
     };
 
-    let resp = reqwest::Client::builder()
-        .redirect(reqwest::redirect::Policy::none())
-        .build()?.post(format!("https://api-user.huami.com/registrations/{path_val}/tokens"))
+    let request = client.post(format!("https://api-user.huami.com/registrations/{path_val}/tokens"))
         .form(&params)
-        .headers(get_headers())
-        .send()
-        .await?;
-    let location = resp.headers().get("location").unwrap();
+        .headers(get_headers());
+    let Some(resp) = send(client, request, dry_run, trace).await? else {
+        return Ok("<access>".to_string());
+    };
+    let location = resp.headers().get("location")
+        .ok_or(format!("HTTP {}", resp.status()))?;
     get_code(String::from_utf8_lossy(location.as_bytes()).trim())
 }
 
 
-async fn get_token<'a>(account: &'a str, access: &'a str) -> Result<(String, String), Box<dyn Error>> {
+#[instrument(name = "stage", skip_all, fields(stage = "token"))]
+async fn get_token<'a>(client: &Client, account: &'a str, access: &'a str, dry_run: bool, trace: &mut Trace) -> Result<(String, String), Box<dyn Error>> {
+    trace.start(Stage::Token);
     let params = if is_email(account) {
         [
             ("app_name", "com.xiaomi.hm.health"),
@@ -69,43 +253,50 @@ async fn get_token<'a>(account: &'a str, access: &'a str) -> Result<(String, Str
         ]
     };
 
-    let resp = reqwest::Client::builder()
-        .redirect(reqwest::redirect::Policy::none())
-        .build()?.post(format!("https://account.huami.com/v2/client/login"))
+    let request = client.post("https://account.huami.com/v2/client/login")
         .form(&params)
-        .headers(get_headers())
-        .send()
-        .await?;
+        .headers(get_headers());
+    let Some(resp) = send(client, request, dry_run, trace).await? else {
+        return Ok(("<login_token>".to_string(), "<user_id>".to_string()));
+    };
     let mut json_value = resp.json::<Value>().await?;
     let error_code = json_value["error_code"].as_str();
     if let Some(code) = error_code {
-        return Err(format!("获取login_token错误：{code}").into());
+        return Err(format!("错误码：{code}").into());
     }
     let token_info = &mut json_value["token_info"];
     let login_token = token_info["login_token"].take();
     let user_id = token_info["user_id"].take();
-    let x = login_token.as_str().unwrap().to_string();
-    let y = user_id.as_str().unwrap().to_string();
-    Ok((x, y))
+    let (Some(login_token), Some(user_id)) = (login_token.as_str(), user_id.as_str()) else {
+        return Err("返回内容中没有login_token或user_id".into());
+    };
+    logging::add_secret(login_token);
+    Ok((login_token.to_string(), user_id.to_string()))
 }
 
-async fn get_app_token(login_token: &str) -> Result<String, Box<dyn Error>> {
+#[instrument(name = "stage", skip_all, fields(stage = "app_token"))]
+async fn get_app_token(client: &Client, login_token: &str, dry_run: bool, trace: &mut Trace) -> Result<String, Box<dyn Error>> {
+    trace.start(Stage::AppToken);
     let url = format!("https://account-cn.huami.com/v1/client/app_tokens?app_name=com.xiaomi.hm.health&dn=api-user.huami.com%2Capi-mifit.huami.com%2Capp-analytics.huami.com&login_token={login_token}");
-    let resp = reqwest::Client::builder()
-        .redirect(reqwest::redirect::Policy::none())
-        .build()?.get(url)
-        .headers(get_headers())
-        .send()
-        .await?;
+    let request = client.get(url)
+        .headers(get_headers());
+    let Some(resp) = send(client, request, dry_run, trace).await? else {
+        return Ok("<app_token>".to_string());
+    };
     let json_value = resp.json::<Value>().await?;
     let error_code = json_value["error_code"].as_str();
     if let Some(code) = error_code {
-        return Err(format!("获取app_token错误：{code}").into());
+        return Err(format!("错误码：{code}").into());
     }
-    Ok(json_value["token_info"]["app_token"].as_str().unwrap().to_string())
+    let app_token = json_value["token_info"]["app_token"].as_str()
+        .ok_or("返回内容中没有app_token")?;
+    logging::add_secret(app_token);
+    Ok(app_token.to_string())
 }
 
-async fn sync_steps(app_token: &str, user_id: &str, steps: u32) -> Result<(), Box<dyn Error>> {
+#[instrument(name = "stage", skip_all, fields(stage = "upload", steps = steps))]
+async fn sync_steps(client: &Client, app_token: &str, user_id: &str, steps: u32, dry_run: bool, trace: &mut Trace) -> Result<(), Box<dyn Error>> {
+    trace.start(Stage::Upload);
     let time = chrono::Utc::now().timestamp_millis();
     let url = format!("https://api-mifit-cn.huami.com/v1/data/band_data.json?&t={time}");
     let step_data = get_step_data(steps);
@@ -118,42 +309,32 @@ async fn sync_steps(app_token: &str, user_id: &str, steps: u32) -> Result<(), Bo
     ];
 
     let mut headers = get_headers();
-    headers.insert("apptoken", HeaderValue::from_str(app_token).unwrap());
-    let resp = reqwest::Client::builder()
-        .redirect(reqwest::redirect::Policy::none())
-        .build()?
-        .post(url)
+    headers.insert("apptoken", HeaderValue::from_str(app_token).map_err(|_| "app_token格式错误")?);
+    let request = client.post(url)
         .headers(headers)
-        .form(&params)
-        .send()
-        .await?;
+        .form(&params);
+    let Some(resp) = send(client, request, dry_run, trace).await? else {
+        return Ok(());
+    };
 
     let json_value = resp.json::<Value>().await?;
-    let code = json_value["code"].as_u64();
-    return match code {
-        None => {
-            Err(format!("更新步数错误").into())
-        }
-        Some(code) => {
-            if code == 1 {
-                Ok(())
-            } else {
-                Err(format!("更新步数错误").into())
-            }
-        }
-    };
+    match json_value["code"].as_u64() {
+        Some(1) => Ok(()),
+        _ => Err(format!("返回码：{}", json_value["code"]).into()),
+    }
 }
 
 fn get_code(location: &str) -> Result<String, Box<dyn Error>> {
-    let url = Url::parse(location).unwrap();
+    let url = Url::parse(location).map_err(|e| format!("跳转地址格式错误：{location}（{e}）"))?;
     let mut parse = url.query_pairs();
     let option = parse.find(|(key, _)| {
         key.eq("access")
     });
     if let Some((_, val)) = option {
+        logging::add_secret(&val);
         Ok(val.to_string())
     } else {
-        Err("登陆失败".into())
+        Err("未返回access，请检查账号和密码".into())
     }
 }
 
@@ -174,3 +355,15 @@ fn get_step_data(steps: u32) -> String {
     s.replace("1-2-3", chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string().as_str())
         .replace("989898", steps.to_string().as_str())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code_from_location() {
+        assert_eq!(get_code("https://s3-us-west-2.amazonaws.com/hm-registration/successsignin.html?region=us-west-2&access=abc&country_code=CN").unwrap(), "abc");
+        assert!(get_code("https://s3-us-west-2.amazonaws.com/hm-registration/successsignin.html?error=0106").unwrap_err().to_string().contains("未返回access"));
+        assert!(get_code("/successsignin.html?access=abc").unwrap_err().to_string().contains("跳转地址格式错误"));
+    }
+}
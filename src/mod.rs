@@ -1,2 +1,25 @@
 pub mod mi;
-pub mod bark;
\ No newline at end of file
+pub mod cli;
+pub mod config;
+pub mod accounts;
+pub mod runner;
+pub mod daemon;
+pub mod api;
+pub mod dashboard;
+pub mod dry_run;
+pub mod strategy;
+pub mod calendar;
+pub mod state;
+pub mod history;
+pub mod metrics;
+pub mod report;
+pub mod github;
+pub mod logging;
+pub mod bark;
+pub mod bark_crypto;
+pub mod notify;
+pub mod matrix;
+pub mod telegram;
+pub mod dingtalk;
+pub mod ntfy;
+pub mod webhook;
\ No newline at end of file
@@ -0,0 +1,306 @@
+use std::error::Error;
+use std::time::Duration;
+use async_trait::async_trait;
+use percent_encoding::percent_decode_str;
+use reqwest::Url;
+use crate::mods::bark::{Bark, BarkApi, BarkAuth};
+use crate::mods::bark_crypto::{BarkEncryption, CipherMode};
+use crate::mods::config::env_var;
+use crate::mods::dingtalk::DingTalk;
+use crate::mods::history::TargetRecord;
+use crate::mods::matrix::Matrix;
+use crate::mods::ntfy::Ntfy;
+use crate::mods::telegram::Telegram;
+use crate::mods::webhook::Webhook;
+
+pub struct Notification {
+    pub title: String,
+    pub body: String,
+    pub group: Option<String>,
+}
+
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    fn name(&self) -> &str;
+
+    async fn notify(&self, notification: &Notification) -> Result<(), Box<dyn Error>>;
+
+    /// 同时返回每个目标的结果，只有一个目标的通知不单独列出
+    async fn notify_targets(&self, notification: &Notification) -> (Result<(), Box<dyn Error>>, Vec<TargetRecord>) {
+        (self.notify(notification).await, Vec::new())
+    }
+
+    /// dry-run时描述将要发送的请求，不进行任何网络请求
+    fn preview(&self, notification: &Notification) -> Result<Vec<String>, Box<dyn Error>> {
+        Ok(vec![format!("{}通知：{} {}", self.name(), notification.title, notification.body)])
+    }
+}
+
+pub fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// 解析 NOTIFY_URLS，每行一个地址，空行和 # 开头的行会被忽略
+pub fn parse_notify_urls(text: &str) -> Result<Vec<Box<dyn Notifier>>, Box<dyn Error>> {
+    let mut notifiers = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let notifier = from_url(line)
+            .map_err(|e| format!("NOTIFY_URLS 第{}行（{}）：{e}", index + 1, mask_url(line)))?;
+        notifiers.push(notifier);
+    }
+    Ok(notifiers)
+}
+
+/// 根据 NOTIFY_URLS、BARK_* 和 MATRIX_* 环境变量创建通知
+pub fn from_env() -> Result<Vec<Box<dyn Notifier>>, Box<dyn Error>> {
+    let mut notifiers = parse_notify_urls(&env_var("NOTIFY_URLS")?.unwrap_or_default())?;
+    if let (Some(bark_server), Some(bark_key)) = (env_var("BARK_SERVER")?, env_var("BARK_KEY")?) {
+        let api = match env_var("BARK_API")? {
+            Some(api) if api.eq_ignore_ascii_case("get") => BarkApi::Get,
+            _ => BarkApi::Post,
+        };
+        let mut bark = Bark::with_keys(bark_server, split_keys(&bark_key))?.with_api(api);
+        if let Some(encrypt_key) = env_var("BARK_ENCRYPT_KEY")? {
+            let mode = parse_cipher_mode(&env_var("BARK_ENCRYPT_MODE")?.unwrap_or("cbc".to_string()))?;
+            bark = bark.with_encryption(BarkEncryption::new(encrypt_key, env_var("BARK_ENCRYPT_IV")?, mode)?);
+        }
+        if let Some(token) = env_var("BARK_TOKEN")? {
+            bark = bark.with_auth(BarkAuth::Bearer(token));
+        } else if let Some(username) = env_var("BARK_USERNAME")? {
+            bark = bark.with_auth(BarkAuth::Basic { username, password: env_var("BARK_PASSWORD")?.unwrap_or_default() });
+        }
+        for header in env_var("BARK_HEADERS")?.unwrap_or_default().lines().filter(|line| !line.trim().is_empty()) {
+            let (name, value) = header.split_once(':').ok_or("BARK_HEADERS格式为 名称: 值")?;
+            bark = bark.with_header(name, value)?;
+        }
+        if let Some(timeout) = env_var("BARK_TIMEOUT")? {
+            let timeout = timeout.parse::<u64>().map_err(|_| format!("BARK_TIMEOUT必须为秒数：{timeout}"))?;
+            bark = bark.with_timeout(Duration::from_secs(timeout))?;
+        }
+        notifiers.push(Box::new(bark));
+    }
+    if let (Some(homeserver), Some(token), Some(room)) = (env_var("MATRIX_HOMESERVER")?, env_var("MATRIX_TOKEN")?, env_var("MATRIX_ROOM")?) {
+        notifiers.push(Box::new(Matrix::new(homeserver, token, room)?));
+    }
+    Ok(notifiers)
+}
+
+pub fn from_url(raw: &str) -> Result<Box<dyn Notifier>, String> {
+    let (scheme, rest) = raw.split_once("://").ok_or("缺少协议头，例如 bark://")?;
+    match scheme {
+        "bark" | "barks" => {
+            let url = parse(raw)?;
+            let keys = split_keys(&decode(url.username()));
+            if keys.is_empty() {
+                return Err("缺少bark key，格式为 bark://key@host，多个设备用逗号分隔".into());
+            }
+            let server = format!("{}{}", base_url(&url, scheme == "barks")?, url.path());
+            let api = match url.query_pairs().find(|(key, _)| key == "api") {
+                None => BarkApi::Post,
+                Some((_, api)) if api == "post" => BarkApi::Post,
+                Some((_, api)) if api == "get" => BarkApi::Get,
+                Some((_, api)) => return Err(format!("不支持的bark api：{api}，可选 post、get")),
+            };
+            let mut bark = Bark::with_keys(server, keys).map_err(|e| e.to_string())?.with_api(api);
+            let query = |name: &str| url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, val)| val.to_string());
+            if let Some(enc_key) = query("enc_key") {
+                let mode = parse_cipher_mode(query("enc_mode").as_deref().unwrap_or("cbc"))?;
+                let encryption = BarkEncryption::new(enc_key, query("enc_iv"), mode).map_err(|e| e.to_string())?;
+                bark = bark.with_encryption(encryption);
+            }
+            if let Some(token) = query("token") {
+                bark = bark.with_auth(BarkAuth::Bearer(token));
+            } else if let Some(username) = query("auth_user") {
+                bark = bark.with_auth(BarkAuth::Basic { username, password: query("auth_pass").unwrap_or_default() });
+            }
+            for (_, header) in url.query_pairs().filter(|(key, _)| key == "header") {
+                let (name, value) = header.split_once(':').ok_or("header格式为 名称:值")?;
+                bark = bark.with_header(name, value).map_err(|e| e.to_string())?;
+            }
+            if let Some(timeout) = query("timeout") {
+                let timeout = timeout.parse::<u64>().map_err(|_| format!("timeout必须为秒数：{timeout}"))?;
+                bark = bark.with_timeout(Duration::from_secs(timeout)).map_err(|e| e.to_string())?;
+            }
+            Ok(Box::new(bark))
+        }
+        "tgram" => {
+            let (token, chat_id) = rest.trim_end_matches('/').split_once('/')
+                .ok_or("格式为 tgram://token/chat_id")?;
+            if token.is_empty() || chat_id.is_empty() {
+                return Err("格式为 tgram://token/chat_id".into());
+            }
+            Ok(Box::new(Telegram::new(token.to_string(), chat_id.to_string()).map_err(|e| e.to_string())?))
+        }
+        "ding" => {
+            let token = rest.split(['?', '/']).next().unwrap_or_default();
+            if token.is_empty() {
+                return Err("缺少access_token，格式为 ding://token?secret=".into());
+            }
+            let url = parse(raw)?;
+            let secret = url.query_pairs()
+                .find(|(key, _)| key == "secret")
+                .map(|(_, val)| val.to_string())
+                .filter(|secret| !secret.is_empty());
+            Ok(Box::new(DingTalk::new(token.to_string(), secret).map_err(|e| e.to_string())?))
+        }
+        "ntfy" | "ntfys" => {
+            let url = parse(raw)?;
+            let (path, topic) = split_last(&url);
+            if topic.is_empty() {
+                return Err("格式为 ntfy://host/topic".into());
+            }
+            let token = Some(decode(url.username())).filter(|token| !token.is_empty());
+            let server = format!("{}{path}", base_url(&url, scheme == "ntfys")?);
+            let topic = decode(topic);
+            Ok(Box::new(Ntfy::new(server, topic, token).map_err(|e| e.to_string())?))
+        }
+        "json" | "jsons" => {
+            let url = parse(raw)?;
+            let mut target = format!("{}{}", base_url(&url, scheme == "jsons")?, url.path());
+            if let Some(query) = url.query() {
+                target = format!("{target}?{query}");
+            }
+            Ok(Box::new(Webhook::new(target).map_err(|e| e.to_string())?))
+        }
+        "matrix" | "matrixs" => {
+            let url = parse(raw)?;
+            let token = decode(url.username());
+            let (path, room) = split_last(&url);
+            if token.is_empty() || room.is_empty() {
+                return Err("格式为 matrix://token@host/!room:server".into());
+            }
+            let homeserver = format!("{}{path}", base_url(&url, scheme == "matrixs")?);
+            Ok(Box::new(Matrix::new(homeserver, token, decode(room)).map_err(|e| e.to_string())?))
+        }
+        _ => Err(format!("不支持的通知协议：{scheme}")),
+    }
+}
+
+pub fn parse_cipher_mode(mode: &str) -> Result<CipherMode, String> {
+    match mode.to_ascii_lowercase().as_str() {
+        "cbc" => Ok(CipherMode::Cbc),
+        "ecb" => Ok(CipherMode::Ecb),
+        _ => Err(format!("不支持的加密模式：{mode}，可选 cbc、ecb")),
+    }
+}
+
+pub fn split_keys(keys: &str) -> Vec<String> {
+    keys.split(',')
+        .map(|key| key.trim().to_string())
+        .filter(|key| !key.is_empty())
+        .collect()
+}
+
+fn mask_url(raw: &str) -> String {
+    match raw.split_once("://") {
+        Some((scheme, _)) => format!("{scheme}://***"),
+        None => "***".to_string(),
+    }
+}
+
+fn parse(raw: &str) -> Result<Url, String> {
+    Url::parse(raw).map_err(|e| format!("地址格式错误：{e}"))
+}
+
+fn base_url(url: &Url, https: bool) -> Result<String, String> {
+    let host = url.host_str().filter(|host| !host.is_empty()).ok_or("缺少主机名")?;
+    let scheme = if https { "https" } else { "http" };
+    Ok(match url.port() {
+        Some(port) => format!("{scheme}://{host}:{port}"),
+        None => format!("{scheme}://{host}"),
+    })
+}
+
+/// 把路径拆成前缀和最后一段，前缀以 / 结尾，例如 /sub/topic 拆成 (/sub/, topic)
+fn split_last(url: &Url) -> (&str, &str) {
+    let path = url.path().trim_end_matches('/');
+    match path.rsplit_once('/') {
+        Some((prefix, last)) => (&url.path()[..prefix.len() + 1], last),
+        None => ("/", path),
+    }
+}
+
+fn decode(s: &str) -> String {
+    percent_decode_str(s).decode_utf8_lossy().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preview_url(raw: &str) -> String {
+        let notifier = from_url(raw).unwrap();
+        let notification = Notification { title: "t".to_string(), body: "b".to_string(), group: None };
+        let preview = notifier.preview(&notification).unwrap().remove(0);
+        preview.lines().next().unwrap().to_string()
+    }
+
+    #[test]
+    fn from_url_schemes() {
+        let cases = [
+            ("bark://key@bark.example.com", "bark POST http://bark.example.com/push"),
+            ("barks://key@example.com:8443/bark/", "bark POST https://example.com:8443/bark/push"),
+            ("bark://k1,k2@example.com?api=get", "bark GET http://example.com/k1/t/b"),
+            ("ntfy://ntfy.sh/steps", "ntfy POST http://ntfy.sh/"),
+            ("ntfys://tk@example.com/ntfy/steps", "ntfy POST https://example.com/ntfy/"),
+            ("json://example.com:8080/hook?key=1", "webhook POST http://example.com:8080/hook?key=1"),
+            ("jsons://example.com/hook", "webhook POST https://example.com/hook"),
+            ("matrix://tk@matrix.org/!room:matrix.org", "matrix PUT http://matrix.org/_matrix/client/v3/rooms/!room:matrix.org/send/m.room.message/"),
+            ("matrixs://tk@example.com/synapse/%23room:example.com", "matrix PUT https://example.com/synapse/_matrix/client/v3/rooms/%23room:example.com/send/m.room.message/"),
+            ("tgram://123:abc/-100", "telegram通知：t b"),
+            ("ding://token?secret=SEC1", "dingtalk通知：t b"),
+            ("ding://token", "dingtalk通知：t b"),
+        ];
+        for (raw, expected) in cases {
+            let actual = preview_url(raw);
+            assert!(actual.starts_with(expected), "{raw}: {actual}");
+        }
+    }
+
+    #[test]
+    fn from_url_errors() {
+        let cases = [
+            ("example.com", "缺少协议头"),
+            ("smtp://example.com", "不支持的通知协议：smtp"),
+            ("bark://example.com", "缺少bark key"),
+            ("bark://,@example.com", "缺少bark key"),
+            ("bark://key@example.com?api=put", "不支持的bark api：put"),
+            ("bark://key@example.com?enc_key=1234567890123456&enc_mode=gcm", "不支持的加密模式：gcm"),
+            ("bark://key@example.com?header=bad", "header格式为"),
+            ("bark://key@example.com?timeout=1s", "timeout必须为秒数"),
+            ("tgram://token", "格式为 tgram://token/chat_id"),
+            ("tgram://token/", "格式为 tgram://token/chat_id"),
+            ("ding://?secret=1", "缺少access_token"),
+            ("ntfy://ntfy.sh", "格式为 ntfy://host/topic"),
+            ("ntfy://ntfy.sh/", "格式为 ntfy://host/topic"),
+            ("matrix://matrix.org/!room:matrix.org", "格式为 matrix://"),
+            ("matrix://tk@matrix.org/", "格式为 matrix://"),
+        ];
+        for (raw, expected) in cases {
+            let err = from_url(raw).err().unwrap_or_else(|| panic!("{raw}应该解析失败"));
+            assert!(err.contains(expected), "{raw}: {err}");
+        }
+    }
+
+    #[test]
+    fn parse_notify_urls_skips_comments_and_masks_errors() {
+        let notifiers = parse_notify_urls("\n# 注释\nbark://key@example.com\n  ntfy://ntfy.sh/steps  \n").unwrap();
+        assert_eq!(notifiers.iter().map(|notifier| notifier.name()).collect::<Vec<_>>(), ["bark", "ntfy"]);
+
+        let err = parse_notify_urls("ntfy://ntfy.sh/steps\nbark://secret@example.com?api=put").err().unwrap().to_string();
+        assert_eq!(err, "NOTIFY_URLS 第2行（bark://***）：不支持的bark api：put，可选 post、get");
+        let err = parse_notify_urls("secret-without-scheme").err().unwrap().to_string();
+        assert!(err.starts_with("NOTIFY_URLS 第1行（***）："), "{err}");
+        assert!(!err.contains("secret"), "{err}");
+    }
+}
@@ -0,0 +1,80 @@
+use std::error::Error;
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder};
+use serde_json::json;
+use crate::mods::dry_run;
+use crate::mods::notify::{Notification, Notifier};
+
+pub struct Ntfy {
+    server: String,
+    topic: String,
+    token: Option<String>,
+    client: Client,
+}
+
+impl Ntfy {
+    pub fn new(server: String, topic: String, token: Option<String>) -> Result<Self, Box<dyn Error>> {
+        Ok(Ntfy {
+            server,
+            topic,
+            token,
+            client: Client::builder().build()?,
+        })
+    }
+
+    pub async fn publish(&self, title: &str, message: &str) -> Result<(), Box<dyn Error>> {
+        let resp = self.build_request(title, message).send().await?;
+        if !resp.status().is_success() {
+            return Err(format!("ntfy同步失败：{}", resp.status().as_u16()).into());
+        }
+        Ok(())
+    }
+
+    fn build_request(&self, title: &str, message: &str) -> RequestBuilder {
+        let request = self.client.post(self.server.as_str())
+            .json(&json!({
+                "topic": self.topic,
+                "title": title,
+                "message": message,
+            }));
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for Ntfy {
+    fn name(&self) -> &str {
+        "ntfy"
+    }
+
+    async fn notify(&self, notification: &Notification) -> Result<(), Box<dyn Error>> {
+        self.publish(&notification.title, &notification.body).await
+    }
+
+    fn preview(&self, notification: &Notification) -> Result<Vec<String>, Box<dyn Error>> {
+        let request = self.build_request(&notification.title, &notification.body).build()?;
+        Ok(vec![format!("ntfy {}", dry_run::describe(&request, &[]))])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    #[test]
+    fn publish_request() {
+        let ntfy = Ntfy::new("https://ntfy.sh/".to_string(), "steps".to_string(), Some("tk".to_string())).unwrap();
+        let request = ntfy.build_request("标题", "内容").build().unwrap();
+        assert_eq!(request.url().as_str(), "https://ntfy.sh/");
+        assert_eq!(request.headers()["authorization"], "Bearer tk");
+        let body: Value = serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(body, json!({ "topic": "steps", "title": "标题", "message": "内容" }));
+
+        let anonymous = Ntfy::new("https://ntfy.sh/".to_string(), "steps".to_string(), None).unwrap();
+        assert!(anonymous.build_request("t", "m").build().unwrap().headers().get("authorization").is_none());
+    }
+}
@@ -0,0 +1,223 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::mods::history::NotifyRecord;
+pub use crate::mods::mi::{ErrorKind, Stage, StageRecord};
+use crate::mods::runner::AccountResult;
+
+/// 报告格式的版本，删除或修改字段时加一，只增加字段时不变
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// `--output json` 输出的一次运行的报告
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RunReport {
+    pub schema_version: u32,
+    /// sync、set、login_check 或 preview
+    pub command: String,
+    pub dry_run: bool,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: u64,
+    /// 所有账户都成功时为true
+    pub success: bool,
+    pub accounts: Vec<AccountReport>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Success,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AccountReport {
+    /// 隐藏了中间部分的账号，如 `138****8000`
+    pub id: String,
+    /// 配置中的name，没有配置时省略
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub status: Status,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub steps: Option<u32>,
+    /// 失败时所在的步骤，加锁失败等没有发出请求的情况下省略
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failed_stage: Option<Stage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_kind: Option<ErrorKind>,
+    /// 失败原因，其中的账号也会被隐藏，stages中的错误同样如此
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// 请求华米接口的次数
+    pub attempts: u32,
+    pub duration_ms: u64,
+    pub stages: Vec<StageRecord>,
+    pub notify: Vec<NotifyReport>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct NotifyReport {
+    pub name: String,
+    pub delivered: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub duration_ms: u64,
+    /// 发送到多个目标时每个目标的结果，如bark的每个设备
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<TargetReport>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TargetReport {
+    /// 隐藏了密钥的目标，如 `abcd***`
+    pub target: String,
+    pub delivered: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl RunReport {
+    pub fn new(command: &str, dry_run: bool, started_at: DateTime<Utc>, results: &[AccountResult]) -> Self {
+        RunReport {
+            schema_version: SCHEMA_VERSION,
+            command: command.to_string(),
+            dry_run,
+            started_at,
+            duration_ms: (Utc::now() - started_at).num_milliseconds().max(0) as u64,
+            success: results.iter().all(|result| result.success),
+            accounts: results.iter().map(AccountReport::from).collect(),
+        }
+    }
+}
+
+impl From<&AccountResult> for AccountReport {
+    fn from(result: &AccountResult) -> Self {
+        let id = mask_id(&result.user);
+        let hide = |e: &String| e.replace(&result.user, &id);
+        AccountReport {
+            name: (result.account != result.user).then(|| result.account.clone()),
+            status: if result.success { Status::Success } else { Status::Failed },
+            steps: result.steps,
+            failed_stage: result.stages.last().filter(|stage| stage.error.is_some()).map(|stage| stage.stage),
+            error_kind: result.error_kind,
+            error: result.error.as_ref().map(hide),
+            attempts: result.attempts,
+            duration_ms: result.duration_ms,
+            stages: result.stages.iter()
+                .map(|stage| StageRecord { error: stage.error.as_ref().map(hide), ..stage.clone() })
+                .collect(),
+            notify: result.notify.iter().map(NotifyReport::from).collect(),
+            id,
+        }
+    }
+}
+
+impl From<&NotifyRecord> for NotifyReport {
+    fn from(record: &NotifyRecord) -> Self {
+        NotifyReport {
+            name: record.name.clone(),
+            delivered: record.error.is_none(),
+            error: record.error.clone(),
+            duration_ms: record.duration_ms,
+            targets: record.targets.iter()
+                .map(|target| TargetReport {
+                    target: target.target.clone(),
+                    delivered: target.error.is_none(),
+                    error: target.error.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// 手机号保留前3位和后4位，邮箱保留第一个字符和域名
+pub fn mask_id(user: &str) -> String {
+    let chars: Vec<char> = user.chars().collect();
+    if let Some((name, domain)) = user.split_once('@') {
+        let first: String = name.chars().take(1).collect();
+        return format!("{first}***@{domain}");
+    }
+    if chars.len() >= 11 {
+        let head: String = chars[..3].iter().collect();
+        let tail: String = chars[chars.len() - 4..].iter().collect();
+        return format!("{head}****{tail}");
+    }
+    match chars.first() {
+        Some(first) => format!("{first}***"),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mods::history::TargetRecord;
+
+    #[test]
+    fn masks_phone_and_email() {
+        assert_eq!(mask_id("13800138000"), "138****8000");
+        assert_eq!(mask_id("someone@example.com"), "s***@example.com");
+        assert_eq!(mask_id("12345"), "1***");
+    }
+
+    #[test]
+    fn schema_v1() {
+        let result = AccountResult {
+            user: "13800138000".to_string(),
+            account: "手机".to_string(),
+            success: false,
+            steps: Some(8000),
+            error: Some("登录失败：https://api-user.huami.com/registrations/+8613800138000/tokens".to_string()),
+            error_kind: Some(ErrorKind::Network),
+            attempts: 1,
+            duration_ms: 120,
+            stages: vec![StageRecord {
+                stage: Stage::Login,
+                status: None,
+                duration_ms: 100,
+                error: Some("+8613800138000: dns error".to_string()),
+            }],
+            notify: vec![NotifyRecord {
+                name: "bark".to_string(),
+                error: Some("efgh***：timeout".to_string()),
+                duration_ms: 20,
+                targets: vec![
+                    TargetRecord { target: "abcd***".to_string(), error: None },
+                    TargetRecord { target: "efgh***".to_string(), error: Some("timeout".to_string()) },
+                ],
+            }],
+        };
+        let mut report = RunReport::new("sync", false, "2025-03-01T08:00:00Z".parse().unwrap(), &[result]);
+        report.duration_ms = 150;
+        let expected = serde_json::json!({
+            "schema_version": 1,
+            "command": "sync",
+            "dry_run": false,
+            "started_at": "2025-03-01T08:00:00Z",
+            "duration_ms": 150,
+            "success": false,
+            "accounts": [{
+                "id": "138****8000",
+                "name": "手机",
+                "status": "failed",
+                "steps": 8000,
+                "failed_stage": "login",
+                "error_kind": "network",
+                "error": "登录失败：https://api-user.huami.com/registrations/+86138****8000/tokens",
+                "attempts": 1,
+                "duration_ms": 120,
+                "stages": [{ "stage": "login", "duration_ms": 100, "error": "+86138****8000: dns error" }],
+                "notify": [{
+                    "name": "bark",
+                    "delivered": false,
+                    "error": "efgh***：timeout",
+                    "duration_ms": 20,
+                    "targets": [
+                        { "target": "abcd***", "delivered": true },
+                        { "target": "efgh***", "delivered": false, "error": "timeout" },
+                    ],
+                }],
+            }],
+        });
+        assert_eq!(serde_json::to_value(&report).unwrap(), expected);
+        assert_eq!(serde_json::from_value::<RunReport>(expected).unwrap(), report);
+    }
+}
@@ -0,0 +1,546 @@
+use std::error::Error;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use serde::Serialize;
+use tracing::{debug, error, info, info_span, instrument, warn, Instrument};
+use crate::mods::calendar::DayKind;
+use crate::mods::config::{Account, Config};
+use crate::mods::daemon;
+use crate::mods::dry_run;
+use crate::mods::history::{History, HistoryQuery, NotifyRecord, SyncRecord};
+use crate::mods::logging;
+use crate::mods::metrics::Metrics;
+use crate::mods::mi::{self, ErrorKind, Session, Stage, StageRecord, Trace};
+use crate::mods::notify::Notification;
+use crate::mods::report;
+use crate::mods::state::{self, SqliteStore, StateStore};
+use crate::mods::strategy::{Clock, SystemClock};
+
+pub struct Runner {
+    pub config: Config,
+    pub dry_run: bool,
+    pub clock: Arc<dyn Clock>,
+    state: Arc<dyn StateStore>,
+    pub history: Arc<History>,
+    pub metrics: Arc<Metrics>,
+    /// 请求华米接口共用的客户端
+    client: reqwest::Client,
+    /// 同步锁的持有者，区分共用状态存储的多个实例
+    owner: String,
+}
+
+/// 同步一个账户最多需要的时间，超过后锁自动释放
+const LOCK_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// 账户今天的同步情况
+#[derive(Serialize)]
+pub struct AccountStatus {
+    /// 隐藏了中间部分的账号
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub enabled: bool,
+    pub date: NaiveDate,
+    pub day_kind: DayKind,
+    /// 今天的最大步数
+    pub target: u32,
+    /// 现在同步时生成的步数
+    pub planned: u32,
+    /// 今天已经同步的最大步数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub synced: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_sync: Option<SyncRecord>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_success: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_sync: Option<DateTime<Utc>>,
+}
+
+/// 一个账户的执行结果，JSON报告见 `report::AccountReport`
+pub struct AccountResult {
+    pub user: String,
+    pub account: String,
+    pub success: bool,
+    pub steps: Option<u32>,
+    pub error: Option<String>,
+    pub error_kind: Option<ErrorKind>,
+    /// 请求华米接口的次数，加锁失败等情况下为0
+    pub attempts: u32,
+    pub duration_ms: u64,
+    pub stages: Vec<StageRecord>,
+    pub notify: Vec<NotifyRecord>,
+}
+
+impl AccountResult {
+    fn new(account: &Account, steps: Option<u32>, result: Result<(), String>) -> Self {
+        AccountResult {
+            user: account.user.clone(),
+            account: account.display_name().to_string(),
+            success: result.is_ok(),
+            steps,
+            error: result.err(),
+            error_kind: None,
+            attempts: 0,
+            duration_ms: 0,
+            stages: Vec::new(),
+            notify: Vec::new(),
+        }
+    }
+
+    fn failed(account: &Account, steps: Option<u32>, kind: ErrorKind, error: String) -> Self {
+        AccountResult {
+            error_kind: Some(kind),
+            ..Self::new(account, steps, Err(error))
+        }
+    }
+
+    fn traced(mut self, trace: Trace, started: Instant) -> Self {
+        self.attempts = 1;
+        self.duration_ms = started.elapsed().as_millis() as u64;
+        self.error_kind = trace.error_kind;
+        self.stages = trace.stages;
+        self
+    }
+
+    pub fn notify_errors(&self) -> Vec<String> {
+        self.notify.iter()
+            .filter_map(|record| record.error.as_ref().map(|e| format!("{}通知失败：{e}", record.name)))
+            .collect()
+    }
+}
+
+impl Runner {
+    pub fn new(config: Config, dry_run: bool) -> Result<Self, Box<dyn Error>> {
+        // dry-run不创建历史记录文件，也不连接状态存储
+        let (state, history): (Arc<dyn StateStore>, History) = if dry_run {
+            (Arc::new(SqliteStore::in_memory()?), History::in_memory()?)
+        } else {
+            let state: Arc<dyn StateStore> = Arc::from(state::from_url(&config.state_store)?);
+            debug!(store = state.name(), "已打开状态存储");
+            (state, History::open(&config.history_file)?)
+        };
+        let history = Arc::new(history);
+        let metrics = Metrics::new();
+        for account in &config.accounts {
+            logging::add_secret(&account.password);
+            if let Some(at) = history.last_success(&account.user)? {
+                metrics.set_last_success(&account.user, account.display_name(), at);
+            }
+        }
+        Ok(Runner {
+            config,
+            dry_run,
+            clock: Arc::new(SystemClock),
+            state,
+            history,
+            metrics: Arc::new(metrics),
+            client: mi::client()?,
+            owner: format!("{}-{}", std::process::id(), rand::random::<u32>()),
+        })
+    }
+
+    /// 按账号或name选择账户，未指定时返回所有启用的账户
+    pub fn select(&self, account: Option<&str>) -> Result<Vec<&Account>, Box<dyn Error>> {
+        match account {
+            Some(name) => {
+                let account = self.config.accounts.iter()
+                    .find(|account| account.user == name || account.name.as_deref() == Some(name))
+                    .ok_or(format!("找不到账户：{name}"))?;
+                Ok(vec![account])
+            }
+            None => Ok(self.config.accounts.iter().filter(|account| account.enabled).collect()),
+        }
+    }
+
+    /// steps为None时按当前时间生成步数
+    pub async fn sync(&self, account: Option<&str>, steps: Option<u32>) -> Result<Vec<AccountResult>, Box<dyn Error>> {
+        let accounts = self.select(account)?;
+        let mut results = Vec::new();
+        for account in accounts {
+            results.push(self.sync_account(account, steps).await);
+        }
+        Ok(results)
+    }
+
+    /// 按当前时间生成步数，不会低于当天已经同步过的步数
+    pub async fn sync_now(&self, account: &Account) -> AccountResult {
+        self.sync_account(account, None).await
+    }
+
+    /// 同步并保存历史记录和指标，加锁失败、读取状态失败时也会记录
+    #[instrument(name = "account", skip_all, fields(account = account.display_name(), steps = steps))]
+    async fn sync_account(&self, account: &Account, steps: Option<u32>) -> AccountResult {
+        let started_at = self.clock.now();
+        let date = self.clock.local_now(account.timezone).date();
+        let started = Instant::now();
+        let (result, strategy) = self.sync_guarded(account, steps).await;
+        if self.dry_run {
+            return result;
+        }
+        let record = SyncRecord {
+            user: account.user.clone(),
+            account: account.display_name().to_string(),
+            date,
+            started_at,
+            steps: result.steps,
+            strategy: strategy.map(str::to_string),
+            success: result.success,
+            error: result.error.clone(),
+            duration_ms: started.elapsed().as_millis() as u64,
+            stages: result.stages.clone(),
+            notify: result.notify.clone(),
+        };
+        self.metrics.observe(&record);
+        let history = self.history.clone();
+        if let Err(e) = blocking(move || history.record(&record)).await {
+            warn!("保存历史记录失败：{e}");
+        }
+        result
+    }
+
+    /// 加锁后同步，避免多个实例同时同步同一个账户，返回结果和生成步数的策略
+    async fn sync_guarded<'a>(&self, account: &'a Account, steps: Option<u32>) -> (AccountResult, Option<&'a str>) {
+        if self.dry_run {
+            return self.sync_planned(account, steps).await;
+        }
+        let (store, lock, owner) = (self.state.clone(), format!("lock:{}", account.user), self.owner.clone());
+        match blocking(move || store.try_lock(&lock, &owner, LOCK_TTL)).await {
+            Ok(true) => {}
+            Ok(false) => return (AccountResult::failed(account, steps, ErrorKind::Locked, "其他实例正在同步该账户".to_string()), None),
+            Err(e) => return (AccountResult::failed(account, steps, ErrorKind::State, format!("加锁失败：{e}")), None),
+        }
+        let result = self.sync_planned(account, steps).await;
+        let (store, lock, owner) = (self.state.clone(), format!("lock:{}", account.user), self.owner.clone());
+        if let Err(e) = blocking(move || store.unlock(&lock, &owner)).await {
+            warn!("释放锁失败：{e}");
+        }
+        result
+    }
+
+    /// steps为None时按当前时间生成步数，持有锁后才读取已同步的步数，避免读取之后其他实例又同步了更多的步数
+    async fn sync_planned<'a>(&self, account: &'a Account, steps: Option<u32>) -> (AccountResult, Option<&'a str>) {
+        if let Some(steps) = steps {
+            return (self.sync_locked(account, steps).await, None);
+        }
+        let now = self.clock.local_now(account.timezone);
+        let strategy = account.strategy_on(self.config.calendar.kind(now.date())).name();
+        let mut steps = self.steps_at(account, now);
+        let (store, user, date) = (self.state.clone(), account.user.clone(), now.date());
+        let synced = match blocking(move || state::synced_steps(store.as_ref(), &user, date)).await {
+            Ok(synced) => synced,
+            Err(e) => return (AccountResult::failed(account, Some(steps), ErrorKind::State, format!("读取已同步步数失败：{e}")), Some(strategy)),
+        };
+        if let Some(synced) = synced.filter(|synced| *synced > steps) {
+            info!(steps, synced, "生成的步数低于今天已同步的步数，改为同步{synced}");
+            steps = synced;
+        }
+        tracing::Span::current().record("steps", steps);
+        (self.sync_locked(account, steps).await, Some(strategy))
+    }
+
+    async fn sync_locked(&self, account: &Account, steps: u32) -> AccountResult {
+        if self.dry_run {
+            self.print_account(account, Some(steps));
+        }
+        let started = Instant::now();
+        let mut trace = Trace::default();
+        let cached = if self.dry_run { None } else { self.cached_session(account).await };
+        let result = mi::update_steps(&self.client, &account.user, &account.password, steps, cached.as_ref(), self.dry_run, &mut trace).await
+            .map_err(|e| e.to_string());
+        if !self.dry_run {
+            self.save_session(account, cached.as_ref(), &result, &trace).await;
+        }
+        let result = result.map(|_| ());
+        match &result {
+            Ok(()) => info!(steps, "同步成功"),
+            Err(e) => error!("同步失败：{e}"),
+        }
+        let mut account_result = AccountResult::new(account, Some(steps), result).traced(trace, started);
+        if account_result.success {
+            if !self.dry_run {
+                self.record_steps(account, steps).await;
+            }
+            account_result.notify = self.notify(account, steps).await;
+        }
+        account_result
+    }
+
+    /// 读取失败时重新登录，不影响同步
+    async fn cached_session(&self, account: &Account) -> Option<Session> {
+        let (store, user) = (self.state.clone(), account.user.clone());
+        blocking(move || state::cached_session(store.as_ref(), &user)).await
+            .unwrap_or_else(|e| {
+                warn!("读取缓存的登录凭据失败：{e}");
+                None
+            })
+    }
+
+    /// 保存新的凭据；缓存的凭据被拒绝后重新登录也失败时删除，避免下次再用
+    async fn save_session(&self, account: &Account, cached: Option<&Session>, result: &Result<Session, String>, trace: &Trace) {
+        let (store, user) = (self.state.clone(), account.user.clone());
+        let saved = match result {
+            Ok(session) if cached != Some(session) => {
+                let session = session.clone();
+                blocking(move || state::save_session(store.as_ref(), &user, &session)).await
+            }
+            Err(_) if cached.is_some() && trace.stages.iter().any(|record| record.stage == Stage::Login) => {
+                blocking(move || state::remove_session(store.as_ref(), &user)).await
+            }
+            _ => return,
+        };
+        if let Err(e) = saved {
+            warn!("保存登录凭据失败：{e}");
+        }
+    }
+
+    async fn notify(&self, account: &Account, steps: u32) -> Vec<NotifyRecord> {
+        let mut records = Vec::new();
+        let notification = Notification {
+            title: "✔小米运动同步🐾".to_string(),
+            body: format!("🏃‍[{}]更新步数成功->{}。", account.display_name(), steps),
+            group: Some("小米运动同步记录".to_string()),
+        };
+        for notifier in &account.notifiers {
+            let span = info_span!("notify", notifier = notifier.name());
+            let started = Instant::now();
+            let (result, targets) = if self.dry_run {
+                let result = notifier.preview(&notification)
+                    .map(|requests| requests.iter().for_each(|request| eprintln!("[dry-run] {request}")));
+                (result, Vec::new())
+            } else {
+                notifier.notify_targets(&notification).instrument(span.clone()).await
+            };
+            let result = result.map_err(|e| e.to_string());
+            if let Err(e) = &result {
+                span.in_scope(|| warn!("通知失败：{e}"));
+            }
+            records.push(NotifyRecord {
+                name: notifier.name().to_string(),
+                error: result.err(),
+                duration_ms: started.elapsed().as_millis() as u64,
+                targets,
+            });
+        }
+        records
+    }
+
+    pub async fn login_check(&self, account: Option<&str>) -> Result<Vec<AccountResult>, Box<dyn Error>> {
+        let mut results = Vec::new();
+        for account in self.select(account)? {
+            if self.dry_run {
+                self.print_account(account, None);
+            }
+            let started = Instant::now();
+            let mut trace = Trace::default();
+            let span = info_span!("account", account = account.display_name());
+            let result = mi::login_check(&self.client, &account.user, &account.password, self.dry_run, &mut trace).instrument(span.clone()).await
+                .map(|_| ())
+                .map_err(|e| e.to_string());
+            match &result {
+                Ok(()) => span.in_scope(|| info!("登录成功")),
+                Err(e) => span.in_scope(|| error!("登录失败：{e}")),
+            }
+            results.push(AccountResult::new(account, None, result).traced(trace, started));
+        }
+        Ok(results)
+    }
+
+    pub fn preview(&self, account: Option<&str>, at: Option<NaiveDateTime>) -> Result<Vec<AccountResult>, Box<dyn Error>> {
+        let results = self.select(account)?
+            .into_iter()
+            .map(|account| {
+                let now = at.unwrap_or_else(|| self.clock.local_now(account.timezone));
+                AccountResult::new(account, Some(self.steps_at(account, now)), Ok(()))
+            })
+            .collect();
+        Ok(results)
+    }
+
+    pub async fn status(&self, account: &Account) -> Result<AccountStatus, Box<dyn Error>> {
+        let now = self.clock.local_now(account.timezone);
+        let today = now.date();
+        let kind = self.config.calendar.kind(today);
+        let (store, history, user) = (self.state.clone(), self.history.clone(), account.user.clone());
+        let (last_sync, last_success, synced) = blocking(move || {
+            let last_sync = history.query(&HistoryQuery {
+                account: Some(&user),
+                limit: Some(1),
+                ..HistoryQuery::default()
+            })?.pop();
+            Ok((last_sync, history.last_success(&user)?, state::synced_steps(store.as_ref(), &user, today)?))
+        }).await?;
+        Ok(AccountStatus {
+            id: report::mask_id(&account.user),
+            name: account.name.clone(),
+            enabled: account.enabled,
+            date: today,
+            day_kind: kind,
+            target: account.max_steps_on(today, kind),
+            planned: self.steps_at(account, now),
+            synced,
+            last_sync,
+            last_success,
+            next_sync: daemon::next_run(&account.schedule, account.timezone, self.clock.now()),
+        })
+    }
+
+    async fn record_steps(&self, account: &Account, steps: u32) {
+        let date = self.clock.local_now(account.timezone).date();
+        let (store, user) = (self.state.clone(), account.user.clone());
+        if let Err(e) = blocking(move || state::record_steps(store.as_ref(), &user, date, steps)).await {
+            warn!("保存已同步步数失败：{e}");
+        }
+    }
+
+    fn print_account(&self, account: &Account, steps: Option<u32>) {
+        let timezone = account.timezone.map(|tz| tz.to_string()).unwrap_or("系统时区".to_string());
+        let notifiers: Vec<&str> = account.notifiers.iter().map(|notifier| notifier.name()).collect();
+        let today = self.clock.local_now(account.timezone).date();
+        let kind = self.config.calendar.kind(today);
+        eprintln!("[dry-run] 账户：{} 名称：{} 密码：{} 今天：{kind} 最大步数：{} 步数策略：{} 时区：{} 通知：{}",
+                  account.user, account.display_name(), dry_run::mask(&account.password),
+                  account.max_steps_on(today, kind), account.strategy_on(kind).name(), timezone, notifiers.join(","));
+        if let Some(steps) = steps {
+            let store = self.config.state_store.split_once("://").map_or("", |(scheme, _)| scheme);
+            eprintln!("[dry-run] 同步步数：{steps}（状态存储：{store}，dry-run时不读写）");
+        }
+    }
+
+    fn steps_at(&self, account: &Account, now: NaiveDateTime) -> u32 {
+        account.steps_at(now, self.config.calendar.kind(now.date()))
+    }
+}
+
+/// 在单独的线程中读写状态存储和历史记录，连接Redis或等待SQLite时不会阻塞其他任务
+pub async fn blocking<T, F>(f: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, Box<dyn Error>> + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || f().map_err(|e| e.to_string())).await
+        .unwrap_or_else(|e| Err(e.to_string()))
+}
+
+/// 解析 `HH:MM[:SS]` 或 `YYYY-MM-DD HH:MM[:SS]`，只有时间时日期取今天
+pub fn parse_time(value: &str) -> Result<NaiveDateTime, Box<dyn Error>> {
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"] {
+        if let Ok(time) = NaiveDateTime::parse_from_str(value, format) {
+            return Ok(time);
+        }
+    }
+    for format in ["%H:%M:%S", "%H:%M"] {
+        if let Ok(time) = NaiveTime::parse_from_str(value, format) {
+            return Ok(chrono::Local::now().date_naive().and_time(time));
+        }
+    }
+    Err(format!("时间格式错误：{value}，应为 HH:MM 或 YYYY-MM-DD HH:MM").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use crate::mods::config::EnvOverrides;
+
+    /// 所有操作都失败，模拟Redis或SQLite不可用
+    struct BrokenStore;
+
+    impl StateStore for BrokenStore {
+        fn name(&self) -> &str {
+            "broken"
+        }
+
+        fn get(&self, _key: &str) -> Result<Option<String>, Box<dyn Error>> {
+            Err("连接被拒绝".into())
+        }
+
+        fn set(&self, _key: &str, _value: &str, _ttl: Option<Duration>) -> Result<(), Box<dyn Error>> {
+            Err("连接被拒绝".into())
+        }
+
+        fn remove(&self, _key: &str) -> Result<(), Box<dyn Error>> {
+            Err("连接被拒绝".into())
+        }
+
+        fn try_lock(&self, _key: &str, _owner: &str, _ttl: Duration) -> Result<bool, Box<dyn Error>> {
+            Err("连接被拒绝".into())
+        }
+
+        fn unlock(&self, _key: &str, _owner: &str) -> Result<(), Box<dyn Error>> {
+            Err("连接被拒绝".into())
+        }
+    }
+
+    /// 使用内存中的状态存储和历史记录，但按非dry-run的流程同步
+    fn runner(state: Arc<dyn StateStore>) -> Runner {
+        let content = "[[account]]\nuser = \"13800138000\"\npassword = \"p\"\nname = \"手机\"\n";
+        let config = Config::parse(content, Path::new("config.toml"), EnvOverrides::default(), Vec::new()).unwrap();
+        Runner { dry_run: false, state, ..Runner::new(config, true).unwrap() }
+    }
+
+    fn assert_recorded(runner: &Runner, error: &str) {
+        let records = runner.history.query(&HistoryQuery::default()).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!((records[0].user.as_str(), records[0].success), ("13800138000", false));
+        assert!(records[0].error.as_deref().unwrap().starts_with(error), "{records:?}");
+        let text = runner.metrics.render().unwrap();
+        for line in [
+            "mi_steps_sync_attempts_total{account=\"手机\"} 1",
+            "mi_steps_sync_failures_total{account=\"手机\",stage=\"unknown\"} 1",
+        ] {
+            assert!(text.lines().any(|l| l == line), "缺少 {line}：\n{text}");
+        }
+    }
+
+    #[tokio::test]
+    async fn records_state_store_failure() {
+        let runner = runner(Arc::new(BrokenStore));
+        let results = runner.sync(None, None).await.unwrap();
+        assert_eq!(results[0].error_kind, Some(ErrorKind::State));
+        assert_recorded(&runner, "加锁失败：连接被拒绝");
+    }
+
+    #[tokio::test]
+    async fn records_lock_contention() {
+        let state = Arc::new(SqliteStore::in_memory().unwrap());
+        state.try_lock("lock:13800138000", "other", LOCK_TTL).unwrap();
+        let runner = runner(state);
+        let results = runner.sync(None, Some(8000)).await.unwrap();
+        assert_eq!(results[0].error_kind, Some(ErrorKind::Locked));
+        assert_recorded(&runner, "其他实例正在同步该账户");
+        assert_eq!(runner.history.query(&HistoryQuery::default()).unwrap()[0].steps, Some(8000));
+    }
+
+    #[tokio::test]
+    async fn keeps_session_until_rejected() {
+        let state = Arc::new(SqliteStore::in_memory().unwrap());
+        let runner = runner(state.clone());
+        let account = &runner.config.accounts[0];
+        let session = |token: &str| Session { user_id: "42".to_string(), app_token: token.to_string() };
+        let stage = |stage| StageRecord { stage, status: None, duration_ms: 0, error: None };
+        let trace = |stages: Vec<Stage>| {
+            let mut trace = Trace::default();
+            trace.stages = stages.into_iter().map(stage).collect();
+            trace
+        };
+
+        runner.save_session(account, None, &Ok(session("a")), &trace(vec![Stage::Login, Stage::Upload])).await;
+        assert_eq!(runner.cached_session(account).await, Some(session("a")));
+
+        // 缓存的凭据上传时网络错误，不重新登录，凭据保留
+        let cached = session("a");
+        runner.save_session(account, Some(&cached), &Err("上传步数失败".to_string()), &trace(vec![Stage::Upload])).await;
+        assert_eq!(runner.cached_session(account).await, Some(session("a")));
+
+        // 被拒绝后重新登录成功，替换为新的凭据
+        runner.save_session(account, Some(&cached), &Ok(session("b")), &trace(vec![Stage::Upload, Stage::Login, Stage::Upload])).await;
+        assert_eq!(runner.cached_session(account).await, Some(session("b")));
+
+        // 被拒绝后重新登录也失败，删除凭据
+        let cached = session("b");
+        runner.save_session(account, Some(&cached), &Err("登录失败".to_string()), &trace(vec![Stage::Upload, Stage::Login])).await;
+        assert_eq!(runner.cached_session(account).await, None);
+    }
+}
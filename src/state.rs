@@ -0,0 +1,514 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use chrono::{NaiveDate, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use crate::mods::mi::Session;
+
+pub const DEFAULT_STATE_FILE: &str = "state.json";
+const REDIS_PREFIX: &str = "mi_steps:";
+/// 步数记录保留两天，足够覆盖跨时区的当天
+const STEPS_TTL: Duration = Duration::from_secs(2 * 24 * 3600);
+/// app_token的有效期没有公开，保存一天，期间被拒绝时会重新登录并覆盖
+const SESSION_TTL: Duration = Duration::from_secs(24 * 3600);
+
+/// 持久化的键值存储，用于保存当天已同步的步数、登录凭据和同步锁
+pub trait StateStore: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn get(&self, key: &str) -> Result<Option<String>, Box<dyn Error>>;
+
+    /// ttl为None时永不过期
+    fn set(&self, key: &str, value: &str, ttl: Option<Duration>) -> Result<(), Box<dyn Error>>;
+
+    /// key不存在时什么也不做
+    fn remove(&self, key: &str) -> Result<(), Box<dyn Error>>;
+
+    /// key不存在或已过期时加锁并返回true，已被owner持有时续期
+    fn try_lock(&self, key: &str, owner: &str, ttl: Duration) -> Result<bool, Box<dyn Error>>;
+
+    /// 只释放owner自己持有的锁
+    fn unlock(&self, key: &str, owner: &str) -> Result<(), Box<dyn Error>>;
+}
+
+/// 根据地址创建存储：`file://state.json`、`sqlite://history.db` 或 `redis://host:6379/0`
+pub fn from_url(url: &str) -> Result<Box<dyn StateStore>, Box<dyn Error>> {
+    let (scheme, rest) = url.split_once("://").ok_or(format!("状态存储地址格式错误：{url}"))?;
+    Ok(match scheme {
+        "file" => Box::new(FileStore::open(Path::new(rest))?),
+        "sqlite" => Box::new(SqliteStore::open(Path::new(rest))?),
+        "redis" | "rediss" => Box::new(RedisStore::open(url)?),
+        _ => return Err(format!("不支持的状态存储：{scheme}").into()),
+    })
+}
+
+fn steps_key(user: &str, date: NaiveDate) -> String {
+    format!("steps:{user}:{date}")
+}
+
+/// 当天已同步的最大步数
+pub fn synced_steps(store: &dyn StateStore, user: &str, date: NaiveDate) -> Result<Option<u32>, Box<dyn Error>> {
+    let value = store.get(&steps_key(user, date))?;
+    Ok(value.and_then(|value| value.parse().ok()))
+}
+
+/// 记录同步的步数，只保留当天的最大值，返回是否有变化
+pub fn record_steps(store: &dyn StateStore, user: &str, date: NaiveDate, steps: u32) -> Result<bool, Box<dyn Error>> {
+    if synced_steps(store, user, date)?.is_some_and(|synced| synced >= steps) {
+        return Ok(false);
+    }
+    store.set(&steps_key(user, date), &steps.to_string(), Some(STEPS_TTL))?;
+    Ok(true)
+}
+
+fn session_key(user: &str) -> String {
+    format!("session:{user}")
+}
+
+/// 上次登录得到的凭据，内容无法解析时当作没有
+pub fn cached_session(store: &dyn StateStore, user: &str) -> Result<Option<Session>, Box<dyn Error>> {
+    let value = store.get(&session_key(user))?;
+    Ok(value.and_then(|value| serde_json::from_str(&value).ok()))
+}
+
+pub fn save_session(store: &dyn StateStore, user: &str, session: &Session) -> Result<(), Box<dyn Error>> {
+    store.set(&session_key(user), &serde_json::to_string(session)?, Some(SESSION_TTL))
+}
+
+pub fn remove_session(store: &dyn StateStore, user: &str) -> Result<(), Box<dyn Error>> {
+    store.remove(&session_key(user))
+}
+
+fn expires_at(ttl: Option<Duration>) -> Option<i64> {
+    ttl.map(|ttl| Utc::now().timestamp_millis() + ttl.as_millis() as i64)
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct FileEntry {
+    value: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_at: Option<i64>,
+}
+
+impl FileEntry {
+    fn expired(&self, now: i64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+/// 保存在本地JSON文件中，适合单机和GitHub Actions缓存
+///
+/// 每次修改都先对 `<文件>.lock` 加文件锁再重新读取，同一台机器上的多个进程可以共用，
+/// 如cron定时执行的sync和常驻的serve
+pub struct FileStore {
+    path: PathBuf,
+}
+
+impl FileStore {
+    /// 文件不存在时从空记录开始
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let store = FileStore {
+            path: path.to_path_buf(),
+        };
+        store.read()?;
+        Ok(store)
+    }
+
+    fn read(&self) -> Result<HashMap<String, FileEntry>, Box<dyn Error>> {
+        match fs::read_to_string(&self.path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| format!("状态文件{}格式错误：{e}", self.path.display()).into()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+            Err(e) => Err(format!("读取状态文件{}失败：{e}", self.path.display()).into()),
+        }
+    }
+
+    /// 持有文件锁时读取、修改并保存，update返回false时不保存
+    fn update<T>(&self, update: impl FnOnce(&mut HashMap<String, FileEntry>) -> (T, bool)) -> Result<T, Box<dyn Error>> {
+        let path = self.lock_path();
+        let lock = OpenOptions::new().create(true).truncate(false).write(true).open(&path)
+            .and_then(|file| file.lock().map(|_| file))
+            .map_err(|e| format!("锁定状态文件{}失败：{e}", path.display()))?;
+        let mut entries = self.read()?;
+        let (value, changed) = update(&mut entries);
+        if changed {
+            self.save(&mut entries)?;
+        }
+        drop(lock);
+        Ok(value)
+    }
+
+    fn lock_path(&self) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".lock");
+        PathBuf::from(path)
+    }
+
+    fn save(&self, entries: &mut HashMap<String, FileEntry>) -> Result<(), Box<dyn Error>> {
+        let now = Utc::now().timestamp_millis();
+        entries.retain(|_, entry| !entry.expired(now));
+        let content = serde_json::to_string_pretty(entries)?;
+        // 先写临时文件再改名，避免写到一半时文件损坏，读取时也就不需要加锁
+        let temp = self.path.with_extension("json.tmp");
+        fs::write(&temp, content)
+            .and_then(|_| fs::rename(&temp, &self.path))
+            .map_err(|e| format!("保存状态文件{}失败：{e}", self.path.display()).into())
+    }
+}
+
+impl StateStore for FileStore {
+    fn name(&self) -> &str {
+        "file"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>, Box<dyn Error>> {
+        let now = Utc::now().timestamp_millis();
+        Ok(self.read()?.remove(key)
+            .filter(|entry| !entry.expired(now))
+            .map(|entry| entry.value))
+    }
+
+    fn set(&self, key: &str, value: &str, ttl: Option<Duration>) -> Result<(), Box<dyn Error>> {
+        self.update(|entries| {
+            entries.insert(key.to_string(), FileEntry { value: value.to_string(), expires_at: expires_at(ttl) });
+            ((), true)
+        })
+    }
+
+    fn remove(&self, key: &str) -> Result<(), Box<dyn Error>> {
+        self.update(|entries| ((), entries.remove(key).is_some()))
+    }
+
+    fn try_lock(&self, key: &str, owner: &str, ttl: Duration) -> Result<bool, Box<dyn Error>> {
+        let now = Utc::now().timestamp_millis();
+        self.update(|entries| {
+            if entries.get(key).is_some_and(|entry| !entry.expired(now) && entry.value != owner) {
+                return (false, false);
+            }
+            entries.insert(key.to_string(), FileEntry { value: owner.to_string(), expires_at: expires_at(Some(ttl)) });
+            (true, true)
+        })
+    }
+
+    fn unlock(&self, key: &str, owner: &str) -> Result<(), Box<dyn Error>> {
+        self.update(|entries| {
+            let owned = entries.get(key).is_some_and(|entry| entry.value == owner);
+            if owned {
+                entries.remove(key);
+            }
+            ((), owned)
+        })
+    }
+}
+
+/// 保存在SQLite数据库中，可以和同步记录使用同一个文件
+pub struct SqliteStore {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let connection = Connection::open(path)
+            .map_err(|e| format!("打开状态数据库{}失败：{e}", path.display()))?;
+        Self::init(connection)
+    }
+
+    /// 不读写任何文件，dry-run时使用
+    pub fn in_memory() -> Result<Self, Box<dyn Error>> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(connection: Connection) -> Result<Self, Box<dyn Error>> {
+        connection.busy_timeout(Duration::from_secs(5))?;
+        connection.execute_batch("CREATE TABLE IF NOT EXISTS state (key TEXT PRIMARY KEY, value TEXT NOT NULL, expires_at INTEGER)")?;
+        Ok(SqliteStore {
+            connection: Mutex::new(connection),
+        })
+    }
+}
+
+impl StateStore for SqliteStore {
+    fn name(&self) -> &str {
+        "sqlite"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>, Box<dyn Error>> {
+        let value = self.connection.lock().unwrap()
+            .query_row(
+                "SELECT value FROM state WHERE key = ?1 AND (expires_at IS NULL OR expires_at > ?2)",
+                params![key, Utc::now().timestamp_millis()],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(value)
+    }
+
+    fn set(&self, key: &str, value: &str, ttl: Option<Duration>) -> Result<(), Box<dyn Error>> {
+        self.connection.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO state (key, value, expires_at) VALUES (?1, ?2, ?3)",
+            params![key, value, expires_at(ttl)],
+        )?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<(), Box<dyn Error>> {
+        self.connection.lock().unwrap().execute("DELETE FROM state WHERE key = ?1", params![key])?;
+        Ok(())
+    }
+
+    fn try_lock(&self, key: &str, owner: &str, ttl: Duration) -> Result<bool, Box<dyn Error>> {
+        // 一条语句完成判断和写入，多个进程共用数据库文件时也不会同时拿到锁
+        let changed = self.connection.lock().unwrap().execute(
+            "INSERT INTO state (key, value, expires_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT (key) DO UPDATE SET value = excluded.value, expires_at = excluded.expires_at
+             WHERE state.value = excluded.value OR (state.expires_at IS NOT NULL AND state.expires_at <= ?4)",
+            params![key, owner, expires_at(Some(ttl)), Utc::now().timestamp_millis()],
+        )?;
+        Ok(changed == 1)
+    }
+
+    fn unlock(&self, key: &str, owner: &str) -> Result<(), Box<dyn Error>> {
+        self.connection.lock().unwrap().execute("DELETE FROM state WHERE key = ?1 AND value = ?2", params![key, owner])?;
+        Ok(())
+    }
+}
+
+/// 保存在Redis中，多个实例可以共用，key带有 `mi_steps:` 前缀
+pub struct RedisStore {
+    client: redis::Client,
+}
+
+impl RedisStore {
+    pub fn open(url: &str) -> Result<Self, Box<dyn Error>> {
+        let client = redis::Client::open(url).map_err(|e| format!("Redis地址错误：{e}"))?;
+        Ok(RedisStore {
+            client,
+        })
+    }
+
+    fn connection(&self) -> Result<redis::Connection, Box<dyn Error>> {
+        self.client.get_connection_with_timeout(Duration::from_secs(5))
+            .map_err(|e| format!("连接Redis失败：{e}").into())
+    }
+}
+
+impl StateStore for RedisStore {
+    fn name(&self) -> &str {
+        "redis"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>, Box<dyn Error>> {
+        Ok(redis::cmd("GET").arg(format!("{REDIS_PREFIX}{key}")).query(&mut self.connection()?)?)
+    }
+
+    fn set(&self, key: &str, value: &str, ttl: Option<Duration>) -> Result<(), Box<dyn Error>> {
+        let mut command = redis::cmd("SET");
+        command.arg(format!("{REDIS_PREFIX}{key}")).arg(value);
+        if let Some(ttl) = ttl {
+            command.arg("PX").arg(ttl.as_millis() as u64);
+        }
+        command.query::<()>(&mut self.connection()?)?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<(), Box<dyn Error>> {
+        redis::cmd("DEL").arg(format!("{REDIS_PREFIX}{key}")).query::<()>(&mut self.connection()?)?;
+        Ok(())
+    }
+
+    fn try_lock(&self, key: &str, owner: &str, ttl: Duration) -> Result<bool, Box<dyn Error>> {
+        let script = redis::Script::new(
+            "if redis.call('GET', KEYS[1]) == ARGV[1] or redis.call('SET', KEYS[1], ARGV[1], 'NX', 'PX', ARGV[2]) then
+                 redis.call('PEXPIRE', KEYS[1], ARGV[2])
+                 return 1
+             end
+             return 0",
+        );
+        let locked: i32 = script.key(format!("{REDIS_PREFIX}{key}"))
+            .arg(owner)
+            .arg(ttl.as_millis() as u64)
+            .invoke(&mut self.connection()?)?;
+        Ok(locked == 1)
+    }
+
+    fn unlock(&self, key: &str, owner: &str) -> Result<(), Box<dyn Error>> {
+        let script = redis::Script::new(
+            "if redis.call('GET', KEYS[1]) == ARGV[1] then return redis.call('DEL', KEYS[1]) end return 0",
+        );
+        script.key(format!("{REDIS_PREFIX}{key}")).arg(owner).invoke::<i32>(&mut self.connection()?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::{Child, Command, Stdio};
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap()
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("mi_steps_{name}_{}", std::process::id()))
+    }
+
+    /// 所有存储共用的测试
+    fn suite(store: &dyn StateStore) {
+        let name = store.name();
+
+        assert_eq!(store.get("missing").unwrap(), None, "{name}");
+        store.set("key", "value", None).unwrap();
+        assert_eq!(store.get("key").unwrap().as_deref(), Some("value"), "{name}");
+        store.set("key", "other", None).unwrap();
+        assert_eq!(store.get("key").unwrap().as_deref(), Some("other"), "{name}");
+        store.remove("key").unwrap();
+        assert_eq!(store.get("key").unwrap(), None, "{name}");
+        store.remove("key").unwrap();
+        store.set("key", "value", None).unwrap();
+
+        store.set("short", "value", Some(Duration::from_millis(50))).unwrap();
+        assert_eq!(store.get("short").unwrap().as_deref(), Some("value"), "{name}");
+        std::thread::sleep(Duration::from_millis(120));
+        assert_eq!(store.get("short").unwrap(), None, "{name}");
+
+        // 当天的步数只增不减，新的一天重新开始
+        assert_eq!(synced_steps(store, "u", date(11)).unwrap(), None, "{name}");
+        assert!(record_steps(store, "u", date(11), 5000).unwrap(), "{name}");
+        assert!(!record_steps(store, "u", date(11), 3000).unwrap(), "{name}");
+        assert!(record_steps(store, "u", date(11), 8000).unwrap(), "{name}");
+        assert_eq!(synced_steps(store, "u", date(11)).unwrap(), Some(8000), "{name}");
+        assert_eq!(synced_steps(store, "u", date(12)).unwrap(), None, "{name}");
+        assert_eq!(synced_steps(store, "other", date(11)).unwrap(), None, "{name}");
+
+        // 凭据按账号保存，失效后删除
+        let session = Session { user_id: "42".to_string(), app_token: "token".to_string() };
+        assert_eq!(cached_session(store, "u").unwrap(), None, "{name}");
+        save_session(store, "u", &session).unwrap();
+        assert_eq!(cached_session(store, "u").unwrap(), Some(session), "{name}");
+        assert_eq!(cached_session(store, "other").unwrap(), None, "{name}");
+        remove_session(store, "u").unwrap();
+        assert_eq!(cached_session(store, "u").unwrap(), None, "{name}");
+
+        // 锁只能被一个owner持有，过期后可以被其他owner拿到
+        let ttl = Duration::from_secs(60);
+        assert!(store.try_lock("lock", "a", ttl).unwrap(), "{name}");
+        assert!(!store.try_lock("lock", "b", ttl).unwrap(), "{name}");
+        assert!(store.try_lock("lock", "a", ttl).unwrap(), "{name}");
+        store.unlock("lock", "b").unwrap();
+        assert!(!store.try_lock("lock", "b", ttl).unwrap(), "{name}");
+        store.unlock("lock", "a").unwrap();
+        assert!(store.try_lock("lock", "b", Duration::from_millis(50)).unwrap(), "{name}");
+        std::thread::sleep(Duration::from_millis(120));
+        assert!(store.try_lock("lock", "a", ttl).unwrap(), "{name}");
+        store.unlock("lock", "a").unwrap();
+    }
+
+    #[test]
+    fn file_store() {
+        let path = temp_path("state.json");
+        let _ = fs::remove_file(&path);
+        suite(&FileStore::open(&path).unwrap());
+
+        // 重新打开后数据还在
+        FileStore::open(&path).unwrap().set("key", "value", None).unwrap();
+        let reopened = FileStore::open(&path).unwrap();
+        assert_eq!(reopened.get("key").unwrap().as_deref(), Some("value"));
+        assert_eq!(synced_steps(&reopened, "u", date(11)).unwrap(), Some(8000));
+        fs::remove_file(reopened.lock_path()).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn file_store_shared_between_instances() {
+        let path = temp_path("shared.json");
+        let (first, second) = (FileStore::open(&path).unwrap(), FileStore::open(&path).unwrap());
+        assert!(first.try_lock("lock", "a", Duration::from_secs(60)).unwrap());
+        assert!(!second.try_lock("lock", "b", Duration::from_secs(60)).unwrap());
+        second.set("key", "value", None).unwrap();
+        first.set("other", "value", None).unwrap();
+        assert_eq!(first.get("key").unwrap().as_deref(), Some("value"));
+        assert_eq!(second.get("other").unwrap().as_deref(), Some("value"));
+        fs::remove_file(first.lock_path()).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn file_store_rejects_broken_file() {
+        let path = temp_path("broken.json");
+        fs::write(&path, "{").unwrap();
+        let result = FileStore::open(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(result.err().unwrap().to_string().contains("格式错误"));
+    }
+
+    #[test]
+    fn sqlite_store() {
+        suite(&SqliteStore::in_memory().unwrap());
+    }
+
+    #[test]
+    fn sqlite_store_shares_file_between_connections() {
+        let path = temp_path("state.db");
+        let (first, second) = (SqliteStore::open(&path).unwrap(), SqliteStore::open(&path).unwrap());
+        assert!(first.try_lock("lock", "a", Duration::from_secs(60)).unwrap());
+        assert!(!second.try_lock("lock", "b", Duration::from_secs(60)).unwrap());
+        first.set("key", "value", None).unwrap();
+        assert_eq!(second.get("key").unwrap().as_deref(), Some("value"));
+        drop((first, second));
+        fs::remove_file(&path).unwrap();
+    }
+
+    struct RedisServer(Child);
+
+    impl Drop for RedisServer {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+
+    /// 设置了TEST_REDIS_URL时使用已有的Redis，否则启动本地的redis-server
+    fn start_redis() -> (Option<RedisServer>, RedisStore) {
+        if let Ok(url) = std::env::var("TEST_REDIS_URL") {
+            return (None, RedisStore::open(&url).unwrap());
+        }
+        let port = 20000 + std::process::id() % 20000;
+        let child = Command::new("redis-server")
+            .args(["--port", &port.to_string(), "--save", "", "--appendonly", "no"])
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("没有找到redis-server，请安装Redis或设置TEST_REDIS_URL");
+        let server = RedisServer(child);
+        let store = RedisStore::open(&format!("redis://127.0.0.1:{port}/")).unwrap();
+        for _ in 0..50 {
+            if store.connection().is_ok() {
+                return (Some(server), store);
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        panic!("redis-server启动失败");
+    }
+
+    #[test]
+    #[ignore = "需要Redis，使用 cargo test -- --ignored 运行"]
+    fn redis_store() {
+        let (_server, store) = start_redis();
+        suite(&store);
+    }
+
+    #[test]
+    fn store_from_url() {
+        let path = temp_path("url.db");
+        assert_eq!(from_url(&format!("sqlite://{}", path.display())).unwrap().name(), "sqlite");
+        fs::remove_file(&path).unwrap();
+        assert_eq!(from_url("file://state.json").unwrap().name(), "file");
+        assert_eq!(from_url("redis://127.0.0.1:6379/0").unwrap().name(), "redis");
+        assert!(from_url("memcached://127.0.0.1").is_err());
+        assert!(from_url("state.json").is_err());
+    }
+}
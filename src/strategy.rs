@@ -0,0 +1,324 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+
+    /// 账户时区下的当前时间，未设置时区时使用系统时区
+    fn local_now(&self, timezone: Option<Tz>) -> NaiveDateTime {
+        match timezone {
+            Some(tz) => self.now().with_timezone(&tz).naive_local(),
+            None => self.now().with_timezone(&chrono::Local).naive_local(),
+        }
+    }
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+#[cfg(test)]
+pub struct FixedClock(pub DateTime<Utc>);
+
+#[cfg(test)]
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
+
+/// 根据当天的时间生成同步步数
+pub trait StepStrategy: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn steps(&self, now: NaiveDateTime, max_steps: u32) -> u32;
+}
+
+/// 从0点线性增长，full_time点达到最大步数
+pub struct Linear {
+    pub full_time: u32,
+}
+
+/// full_time为0时一开始就是最大步数，超过24时当天达不到最大步数
+pub fn check_full_time(full_time: u32) -> Result<u32, String> {
+    if (1..=24).contains(&full_time) {
+        Ok(full_time)
+    } else {
+        Err(format!("full_time必须在1到24之间：{full_time}"))
+    }
+}
+
+impl StepStrategy for Linear {
+    fn name(&self) -> &str {
+        "linear"
+    }
+
+    fn steps(&self, now: NaiveDateTime, max_steps: u32) -> u32 {
+        let millis = millis_of_day(now.time());
+        let all_millis = self.full_time * 3600000;
+
+        if millis < all_millis as i64 {
+            let rate = millis as f64 / all_millis as f64;
+            let step = rate * max_steps as f64;
+            step as u32
+        } else { max_steps }
+    }
+}
+
+/// 按 (时间, 比例) 折线插值，0点为0，最后一个点之后保持最后的比例
+pub struct Piecewise {
+    points: Vec<(NaiveTime, f64)>,
+}
+
+impl Piecewise {
+    pub fn new(mut points: Vec<(NaiveTime, f64)>) -> Result<Self, String> {
+        if points.is_empty() {
+            return Err("piecewise至少需要一个点".into());
+        }
+        if let Some((_, fraction)) = points.iter().find(|(_, fraction)| !(0.0..=1.0).contains(fraction)) {
+            return Err(format!("piecewise的比例必须在0到1之间：{fraction}"));
+        }
+        points.sort_by_key(|(time, _)| *time);
+        Ok(Piecewise {
+            points,
+        })
+    }
+}
+
+impl StepStrategy for Piecewise {
+    fn name(&self) -> &str {
+        "piecewise"
+    }
+
+    fn steps(&self, now: NaiveDateTime, max_steps: u32) -> u32 {
+        let millis = millis_of_day(now.time());
+        let mut previous = (0i64, 0.0);
+        for (time, fraction) in &self.points {
+            let point = (millis_of_day(*time), *fraction);
+            if millis < point.0 {
+                let rate = (millis - previous.0) as f64 / (point.0 - previous.0) as f64;
+                let fraction = previous.1 + (point.1 - previous.1) * rate;
+                return (fraction * max_steps as f64) as u32;
+            }
+            previous = point;
+        }
+        (previous.1 * max_steps as f64) as u32
+    }
+}
+
+/// start到end之间按S型曲线增长，steepness越大中间越陡
+pub struct SCurve {
+    start: NaiveTime,
+    end: NaiveTime,
+    steepness: f64,
+}
+
+impl SCurve {
+    pub fn new(start: NaiveTime, end: NaiveTime, steepness: f64) -> Result<Self, String> {
+        if start >= end {
+            return Err("s_curve的start必须早于end".into());
+        }
+        if steepness <= 0.0 {
+            return Err(format!("s_curve的steepness必须大于0：{steepness}"));
+        }
+        Ok(SCurve {
+            start,
+            end,
+            steepness,
+        })
+    }
+}
+
+impl StepStrategy for SCurve {
+    fn name(&self) -> &str {
+        "s_curve"
+    }
+
+    fn steps(&self, now: NaiveDateTime, max_steps: u32) -> u32 {
+        let (start, end) = (millis_of_day(self.start), millis_of_day(self.end));
+        let x = ((millis_of_day(now.time()) - start) as f64 / (end - start) as f64).clamp(0.0, 1.0);
+        let sigmoid = |x: f64| 1.0 / (1.0 + (-self.steepness * (x - 0.5)).exp());
+        // 归一化，保证start时为0，end时为1
+        let fraction = (sigmoid(x) - sigmoid(0.0)) / (sigmoid(1.0) - sigmoid(0.0));
+        (fraction * max_steps as f64).round() as u32
+    }
+}
+
+/// 固定步数，忽略最大步数
+pub struct Fixed {
+    pub steps: u32,
+}
+
+impl StepStrategy for Fixed {
+    fn name(&self) -> &str {
+        "fixed"
+    }
+
+    fn steps(&self, _now: NaiveDateTime, _max_steps: u32) -> u32 {
+        self.steps
+    }
+}
+
+/// 配置文件中的strategy，例如 `strategy = { type = "s_curve", start = "07:00", end = "21:00" }`
+#[derive(Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum StrategyConfig {
+    Linear {
+        full_time: Option<u32>,
+    },
+    Piecewise {
+        points: Vec<(String, f64)>,
+    },
+    SCurve {
+        start: String,
+        end: String,
+        steepness: Option<f64>,
+    },
+    Fixed {
+        steps: u32,
+    },
+}
+
+impl StrategyConfig {
+    /// full_time为linear未单独设置时使用的满步数时间
+    pub fn build(&self, full_time: u32) -> Result<Box<dyn StepStrategy>, String> {
+        Ok(match self {
+            StrategyConfig::Linear { full_time: own } => Box::new(Linear { full_time: check_full_time(own.unwrap_or(full_time))? }),
+            StrategyConfig::Piecewise { points } => {
+                let points = points.iter()
+                    .map(|(time, fraction)| Ok((parse_time(time)?, *fraction)))
+                    .collect::<Result<Vec<_>, String>>()?;
+                Box::new(Piecewise::new(points)?)
+            }
+            StrategyConfig::SCurve { start, end, steepness } => {
+                Box::new(SCurve::new(parse_time(start)?, parse_time(end)?, steepness.unwrap_or(10.0))?)
+            }
+            StrategyConfig::Fixed { steps } => Box::new(Fixed { steps: *steps }),
+        })
+    }
+}
+
+/// 当天的目标步数，在 [min, max] 中按账号和日期取一个固定的随机值，同一天多次运行结果相同
+pub fn daily_target(user: &str, date: NaiveDate, (min, max): (u32, u32)) -> u32 {
+    let digest = Sha256::digest(format!("{user}#{date}"));
+    let seed = u64::from_be_bytes(digest[..8].try_into().unwrap());
+    min + (seed % (max as u64 - min as u64 + 1)) as u32
+}
+
+fn parse_time(value: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(value, "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(value, "%H:%M:%S"))
+        .map_err(|_| format!("时间格式错误：{value}，应为 HH:MM"))
+}
+
+fn millis_of_day(time: NaiveTime) -> i64 {
+    time.num_seconds_from_midnight() as i64 * 1000 + (time.nanosecond() / 1_000_000) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 所有用例都以北京时间计算
+    fn at(clock_utc: &str) -> NaiveDateTime {
+        let clock = FixedClock(DateTime::parse_from_rfc3339(clock_utc).unwrap().with_timezone(&Utc));
+        clock.local_now(Some(chrono_tz::Asia::Shanghai))
+    }
+
+    #[test]
+    fn linear_ramps_until_full_time() {
+        let strategy = Linear { full_time: 16 };
+        // 北京时间 00:00、08:00、16:00、20:00
+        assert_eq!(strategy.steps(at("2024-03-10T16:00:00Z"), 10000), 0);
+        assert_eq!(strategy.steps(at("2024-03-11T00:00:00Z"), 10000), 5000);
+        assert_eq!(strategy.steps(at("2024-03-11T08:00:00Z"), 10000), 10000);
+        assert_eq!(strategy.steps(at("2024-03-11T12:00:00Z"), 10000), 10000);
+    }
+
+    #[test]
+    fn piecewise_interpolates_between_points() {
+        let config = StrategyConfig::Piecewise {
+            points: vec![("12:00".to_string(), 0.6), ("06:00".to_string(), 0.1), ("20:00".to_string(), 1.0)],
+        };
+        let strategy = config.build(17).unwrap();
+        assert_eq!(strategy.name(), "piecewise");
+        // 03:00 在 0点(0) 和 06:00(0.1) 之间
+        assert_eq!(strategy.steps(at("2024-03-10T19:00:00Z"), 10000), 500);
+        // 09:00 在 06:00(0.1) 和 12:00(0.6) 之间
+        assert_eq!(strategy.steps(at("2024-03-11T01:00:00Z"), 10000), 3500);
+        // 16:00 在 12:00(0.6) 和 20:00(1.0) 之间
+        assert_eq!(strategy.steps(at("2024-03-11T08:00:00Z"), 10000), 8000);
+        // 22:00 之后保持最后的比例
+        assert_eq!(strategy.steps(at("2024-03-11T14:00:00Z"), 10000), 10000);
+    }
+
+    #[test]
+    fn piecewise_rejects_bad_points() {
+        assert!(Piecewise::new(vec![]).is_err());
+        assert!(Piecewise::new(vec![(NaiveTime::from_hms_opt(8, 0, 0).unwrap(), 1.5)]).is_err());
+        let config = StrategyConfig::Piecewise { points: vec![("8点".to_string(), 0.5)] };
+        assert!(config.build(17).is_err());
+    }
+
+    #[test]
+    fn s_curve_is_symmetric_and_bounded() {
+        let strategy = StrategyConfig::SCurve {
+            start: "08:00".to_string(),
+            end: "20:00".to_string(),
+            steepness: None,
+        }.build(17).unwrap();
+        // 07:00、08:00、14:00、20:00、22:00
+        assert_eq!(strategy.steps(at("2024-03-10T23:00:00Z"), 10000), 0);
+        assert_eq!(strategy.steps(at("2024-03-11T00:00:00Z"), 10000), 0);
+        assert_eq!(strategy.steps(at("2024-03-11T06:00:00Z"), 10000), 5000);
+        assert_eq!(strategy.steps(at("2024-03-11T12:00:00Z"), 10000), 10000);
+        assert_eq!(strategy.steps(at("2024-03-11T14:00:00Z"), 10000), 10000);
+        // 中间比两端增长得快
+        let early = strategy.steps(at("2024-03-11T01:00:00Z"), 10000);
+        let middle = strategy.steps(at("2024-03-11T07:00:00Z"), 10000) - 5000;
+        assert!(early < middle, "{early} {middle}");
+    }
+
+    #[test]
+    fn s_curve_rejects_bad_range() {
+        let time = |hour| NaiveTime::from_hms_opt(hour, 0, 0).unwrap();
+        assert!(SCurve::new(time(20), time(8), 10.0).is_err());
+        assert!(SCurve::new(time(8), time(20), 0.0).is_err());
+    }
+
+    #[test]
+    fn fixed_ignores_time_and_max() {
+        let strategy = StrategyConfig::Fixed { steps: 12345 }.build(17).unwrap();
+        assert_eq!(strategy.steps(at("2024-03-10T16:00:00Z"), 100), 12345);
+        assert_eq!(strategy.steps(at("2024-03-11T12:00:00Z"), 100), 12345);
+    }
+
+    #[test]
+    fn daily_target_is_stable_within_a_day() {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 11).unwrap();
+        let target = daily_target("13800138000", date, (8000, 15000));
+        assert!((8000..=15000).contains(&target), "{target}");
+        assert_eq!(daily_target("13800138000", date, (8000, 15000)), target);
+        assert_eq!(daily_target("13800138000", date, (9000, 9000)), 9000);
+
+        // 不同日期、不同账户的目标不全相同
+        let targets: std::collections::HashSet<u32> = (1..=10)
+            .map(|day| daily_target("13800138000", NaiveDate::from_ymd_opt(2024, 3, day).unwrap(), (8000, 15000)))
+            .chain(["a", "b", "c"].map(|user| daily_target(user, date, (8000, 15000))))
+            .collect();
+        assert!(targets.len() > 5, "{targets:?}");
+    }
+
+    #[test]
+    fn linear_config_defaults_to_full_time() {
+        let strategy = StrategyConfig::Linear { full_time: None }.build(8).unwrap();
+        // 北京时间 04:00
+        assert_eq!(strategy.steps(at("2024-03-10T20:00:00Z"), 1000), 500);
+    }
+}
@@ -0,0 +1,71 @@
+use std::error::Error;
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder};
+use serde_json::{json, Value};
+use crate::mods::notify::{escape_html, Notification, Notifier};
+
+pub struct Telegram {
+    token: String,
+    chat_id: String,
+    client: Client,
+}
+
+impl Telegram {
+    pub fn new(token: String, chat_id: String) -> Result<Self, Box<dyn Error>> {
+        Ok(Telegram {
+            token,
+            chat_id,
+            client: Client::builder().build()?,
+        })
+    }
+
+    pub async fn send_message(&self, text: &str) -> Result<(), Box<dyn Error>> {
+        let resp = self.build_request(text).send().await?;
+
+        let json_value = resp.json::<Value>().await?;
+        if json_value["ok"].as_bool() != Some(true) {
+            let description = json_value["description"].as_str().unwrap_or_default();
+            return Err(format!("telegram同步失败：{description}").into());
+        }
+        Ok(())
+    }
+
+    fn build_request(&self, text: &str) -> RequestBuilder {
+        self.client.post(format!("https://api.telegram.org/bot{}/sendMessage", self.token))
+            .json(&json!({
+                "chat_id": self.chat_id,
+                "text": text,
+                "parse_mode": "HTML",
+            }))
+    }
+
+    fn format_html(notification: &Notification) -> String {
+        format!("<b>{}</b>\n{}", escape_html(&notification.title), escape_html(&notification.body))
+    }
+}
+
+#[async_trait]
+impl Notifier for Telegram {
+    fn name(&self) -> &str {
+        "telegram"
+    }
+
+    async fn notify(&self, notification: &Notification) -> Result<(), Box<dyn Error>> {
+        self.send_message(&Self::format_html(notification)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn html_message() {
+        let telegram = Telegram::new("123:abc".to_string(), "-100".to_string()).unwrap();
+        let notification = Notification { title: "<同步>".to_string(), body: "a & b".to_string(), group: None };
+        let request = telegram.build_request(&Telegram::format_html(&notification)).build().unwrap();
+        assert_eq!(request.url().as_str(), "https://api.telegram.org/bot123:abc/sendMessage");
+        let body: Value = serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(body, json!({ "chat_id": "-100", "text": "<b>&lt;同步&gt;</b>\na &amp; b", "parse_mode": "HTML" }));
+    }
+}
@@ -0,0 +1,64 @@
+use std::error::Error;
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder};
+use serde_json::json;
+use crate::mods::dry_run;
+use crate::mods::notify::{Notification, Notifier};
+
+pub struct Webhook {
+    url: String,
+    client: Client,
+}
+
+impl Webhook {
+    pub fn new(url: String) -> Result<Self, Box<dyn Error>> {
+        Ok(Webhook {
+            url,
+            client: Client::builder().build()?,
+        })
+    }
+
+    fn build_request(&self, notification: &Notification) -> RequestBuilder {
+        self.client.post(self.url.as_str())
+            .json(&json!({
+                "title": notification.title,
+                "body": notification.body,
+                "group": notification.group,
+            }))
+    }
+}
+
+#[async_trait]
+impl Notifier for Webhook {
+    fn name(&self) -> &str {
+        "json"
+    }
+
+    async fn notify(&self, notification: &Notification) -> Result<(), Box<dyn Error>> {
+        let resp = self.build_request(notification).send().await?;
+        if !resp.status().is_success() {
+            return Err(format!("webhook同步失败：{}", resp.status().as_u16()).into());
+        }
+        Ok(())
+    }
+
+    fn preview(&self, notification: &Notification) -> Result<Vec<String>, Box<dyn Error>> {
+        Ok(vec![format!("webhook {}", dry_run::describe(&self.build_request(notification).build()?, &[]))])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    #[test]
+    fn json_body() {
+        let webhook = Webhook::new("https://example.com/hook?key=1".to_string()).unwrap();
+        let notification = Notification { title: "标题".to_string(), body: "内容".to_string(), group: Some("分组".to_string()) };
+        let request = webhook.build_request(&notification).build().unwrap();
+        assert_eq!(request.url().as_str(), "https://example.com/hook?key=1");
+        let body: Value = serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(body, json!({ "title": "标题", "body": "内容", "group": "分组" }));
+    }
+}
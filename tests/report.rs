@@ -0,0 +1,101 @@
+//! 以外部依赖的方式使用运行报告，字段变化时这里需要同步修改 `SCHEMA_VERSION`
+
+use mi_steps::report::{
+    AccountReport, ErrorKind, NotifyReport, RunReport, Stage, StageRecord, Status, TargetReport, SCHEMA_VERSION,
+};
+use serde_json::json;
+
+fn report() -> RunReport {
+    RunReport {
+        schema_version: SCHEMA_VERSION,
+        command: "sync".to_string(),
+        dry_run: false,
+        started_at: "2025-03-01T08:00:00Z".parse().unwrap(),
+        duration_ms: 1500,
+        success: false,
+        accounts: vec![
+            AccountReport {
+                id: "138****8000".to_string(),
+                name: Some("手机".to_string()),
+                status: Status::Success,
+                steps: Some(8000),
+                failed_stage: None,
+                error_kind: None,
+                error: None,
+                attempts: 1,
+                duration_ms: 900,
+                stages: vec![StageRecord { stage: Stage::Upload, status: Some(200), duration_ms: 300, error: None }],
+                notify: vec![NotifyReport {
+                    name: "bark".to_string(),
+                    delivered: false,
+                    error: Some("efgh***：timeout".to_string()),
+                    duration_ms: 20,
+                    targets: vec![
+                        TargetReport { target: "abcd***".to_string(), delivered: true, error: None },
+                        TargetReport { target: "efgh***".to_string(), delivered: false, error: Some("timeout".to_string()) },
+                    ],
+                }],
+            },
+            AccountReport {
+                id: "s***@example.com".to_string(),
+                name: None,
+                status: Status::Failed,
+                steps: None,
+                failed_stage: None,
+                error_kind: Some(ErrorKind::Locked),
+                error: Some("其他实例正在同步该账户".to_string()),
+                attempts: 0,
+                duration_ms: 5,
+                stages: Vec::new(),
+                notify: Vec::new(),
+            },
+        ],
+    }
+}
+
+#[test]
+fn schema_v1_round_trip() {
+    assert_eq!(SCHEMA_VERSION, 1);
+    let expected = json!({
+        "schema_version": 1,
+        "command": "sync",
+        "dry_run": false,
+        "started_at": "2025-03-01T08:00:00Z",
+        "duration_ms": 1500,
+        "success": false,
+        "accounts": [
+            {
+                "id": "138****8000",
+                "name": "手机",
+                "status": "success",
+                "steps": 8000,
+                "attempts": 1,
+                "duration_ms": 900,
+                "stages": [{ "stage": "upload", "status": 200, "duration_ms": 300 }],
+                "notify": [{
+                    "name": "bark",
+                    "delivered": false,
+                    "error": "efgh***：timeout",
+                    "duration_ms": 20,
+                    "targets": [
+                        { "target": "abcd***", "delivered": true },
+                        { "target": "efgh***", "delivered": false, "error": "timeout" },
+                    ],
+                }],
+            },
+            {
+                "id": "s***@example.com",
+                "status": "failed",
+                "error_kind": "locked",
+                "error": "其他实例正在同步该账户",
+                "attempts": 0,
+                "duration_ms": 5,
+                "stages": [],
+                "notify": [],
+            },
+        ],
+    });
+    let text = serde_json::to_string(&report()).unwrap();
+    assert_eq!(serde_json::from_str::<serde_json::Value>(&text).unwrap(), expected);
+    assert_eq!(serde_json::from_str::<RunReport>(&text).unwrap(), report());
+}